@@ -0,0 +1,131 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::process::exit;
+
+use clap::{Parser, Subcommand};
+use rust_notes::k8s::api::HttpClient;
+use rust_notes::k8s::backup::{backup_namespace, restore_namespace, RestoreOptions};
+use rust_notes::k8s::manifests::ApplyOptions;
+use rust_notes::k8s::models::HttpKubeConfig;
+
+// 备份命名空间并恢复到另一个命名空间, 例如:
+// cargo run --example k8s_backup -- backup -n default -d /tmp/default -r deployments.apps -r services
+// cargo run --example k8s_backup -- restore -d /tmp/default -n default-copy
+#[derive(Parser)]
+#[command(name = "k8s-backup")]
+#[command(author = "tomoncle")]
+#[command(version = "1.0")]
+#[command(about = "将命名空间中的资源导出为 YAML 文件, 或者从导出的目录恢复.", long_about = None)]
+struct Args {
+    /// kubeconfig 文件路径, 默认使用 KUBECONFIG 环境变量或 ~/.kube/config
+    #[arg(short, long)]
+    kubeconfig: Option<String>,
+
+    /// 使用的 context, 默认为 current-context
+    #[arg(short, long)]
+    context: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 导出命名空间中的资源, 每个对象一个文件
+    Backup {
+        /// 备份的命名空间
+        #[arg(short, long)]
+        namespace: String,
+
+        /// 备份目录
+        #[arg(short, long)]
+        dir: String,
+
+        /// 资源类型, 例如 Deployment 或 deployments.apps, 可以指定多个, 默认为所有资源
+        #[arg(short, long)]
+        resource: Vec<String>,
+    },
+    /// 按依赖顺序恢复备份目录中的资源
+    Restore {
+        /// 备份目录
+        #[arg(short, long)]
+        dir: String,
+
+        /// 恢复到的命名空间, 默认为备份时的命名空间
+        #[arg(short, long)]
+        namespace: Option<String>,
+
+        /// 只在服务端校验, 不持久化
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+fn main() {
+    let args = Args::parse();
+    let config = match (&args.kubeconfig, &args.context) {
+        (Some(path), Some(context)) => HttpKubeConfig::read_from_context(path, context),
+        (Some(path), None) => HttpKubeConfig::read_from(path),
+        (None, context) => HttpKubeConfig::infer_context(context.as_deref()),
+    };
+    let result = config
+        .and_then(HttpClient::new)
+        .and_then(|client| match args.command {
+            Command::Backup {
+                namespace,
+                dir,
+                resource,
+            } => {
+                let kinds: Vec<&str> = resource.iter().map(String::as_str).collect();
+                let files = backup_namespace(&client, &namespace, &kinds, dir)?;
+                for file in &files {
+                    println!("{}", file.display());
+                }
+                println!("{} objects exported", files.len());
+                Ok(())
+            }
+            Command::Restore {
+                dir,
+                namespace,
+                dry_run,
+            } => {
+                let options = RestoreOptions {
+                    namespace,
+                    apply: ApplyOptions {
+                        dry_run,
+                        ..Default::default()
+                    },
+                };
+                for result in restore_namespace(&client, dir, &options)? {
+                    println!("{}", result);
+                }
+                Ok(())
+            }
+        });
+    if let Err(e) = result {
+        eprintln!("执行失败: {}", e);
+        exit(1);
+    }
+}
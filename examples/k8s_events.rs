@@ -0,0 +1,106 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::io::{stdout, IsTerminal};
+use std::process::exit;
+use std::sync::Arc;
+
+use clap::Parser;
+use rust_notes::k8s::api::HttpClient;
+use rust_notes::k8s::events::EventTailer;
+use rust_notes::k8s::models::HttpKubeConfig;
+
+// 实时输出集群事件, 例如:
+// cargo run --example k8s_events -- -n default -n kube-system --type Warning --kind Pod
+#[derive(Parser)]
+#[command(name = "k8s-events")]
+#[command(author = "tomoncle")]
+#[command(version = "1.0")]
+#[command(about = "实时输出 events.k8s.io/v1 事件, 合并重复的事件, 类似 kubectl get events -w.", long_about = None)]
+struct Args {
+    /// kubeconfig 文件路径, 默认使用 KUBECONFIG 环境变量或 ~/.kube/config
+    #[arg(short, long)]
+    kubeconfig: Option<String>,
+
+    /// 使用的 context, 默认为 current-context
+    #[arg(short, long)]
+    context: Option<String>,
+
+    /// 命名空间, 可以指定多个, 默认为所有命名空间
+    #[arg(short, long)]
+    namespace: Vec<String>,
+
+    /// 事件类型: Normal, Warning, 可以指定多个
+    #[arg(short = 't', long = "type")]
+    event_type: Vec<String>,
+
+    /// 关联对象的类型, 例如 Pod, 可以指定多个
+    #[arg(long)]
+    kind: Vec<String>,
+
+    /// 事件原因, 例如 BackOff, 可以指定多个
+    #[arg(short, long)]
+    reason: Vec<String>,
+
+    /// 不使用颜色, 输出不是终端时默认不使用颜色
+    #[arg(long)]
+    no_color: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    let config = match (&args.kubeconfig, &args.context) {
+        (Some(path), Some(context)) => HttpKubeConfig::read_from_context(path, context),
+        (Some(path), None) => HttpKubeConfig::read_from(path),
+        (None, context) => HttpKubeConfig::infer_context(context.as_deref()),
+    };
+    let color = !args.no_color && stdout().is_terminal();
+    let result = config.and_then(HttpClient::new).and_then(|client| {
+        let mut tailer = EventTailer::new(Arc::new(client));
+        for namespace in &args.namespace {
+            tailer = tailer.namespace(namespace);
+        }
+        for event_type in &args.event_type {
+            tailer = tailer.event_type(event_type);
+        }
+        for kind in &args.kind {
+            tailer = tailer.kind(kind);
+        }
+        for reason in &args.reason {
+            tailer = tailer.reason(reason);
+        }
+        tailer.run(|line| {
+            if color {
+                println!("{}", line.colored());
+            } else {
+                println!("{}", line);
+            }
+            true
+        })
+    });
+    if let Err(e) = result {
+        eprintln!("监听事件失败: {}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,109 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::io::{stderr, stdin, stdout, BufRead};
+use std::process::exit;
+use std::sync::mpsc;
+
+use clap::Parser;
+use rust_notes::k8s::api::HttpClient;
+use rust_notes::k8s::exec::{ExecInput, ExecParams};
+use rust_notes::k8s::models::HttpKubeConfig;
+
+// 在容器中执行命令, 例如:
+// cargo run --example k8s_exec -- nginx-0 -- nginx -v
+// cargo run --example k8s_exec -- -i nginx-0 -- sh
+#[derive(Parser)]
+#[command(name = "k8s-exec")]
+#[command(author = "tomoncle")]
+#[command(version = "1.0")]
+#[command(about = "在 Pod 的容器中执行命令, 类似 kubectl exec.", long_about = None)]
+struct Args {
+    /// kubeconfig 文件路径, 默认使用 KUBECONFIG 环境变量或 ~/.kube/config
+    #[arg(short, long)]
+    kubeconfig: Option<String>,
+
+    /// 命名空间, 默认为 context 的命名空间
+    #[arg(short, long)]
+    namespace: Option<String>,
+
+    /// 容器名称
+    #[arg(short, long)]
+    container: Option<String>,
+
+    /// 交互模式, 按行发送标准输入
+    #[arg(short, long)]
+    interactive: bool,
+
+    pod: String,
+
+    #[arg(last = true, required = true)]
+    command: Vec<String>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let config = match &args.kubeconfig {
+        Some(path) => HttpKubeConfig::read_from(path),
+        None => HttpKubeConfig::infer(),
+    };
+    let mut params = ExecParams::new();
+    if let Some(container) = &args.container {
+        params = params.container(container);
+    }
+    if args.interactive {
+        params = params.stdin();
+    }
+    let command: Vec<&str> = args.command.iter().map(String::as_str).collect();
+    let session = config
+        .and_then(HttpClient::new)
+        .and_then(|client| client.exec(args.namespace.as_deref(), &args.pod, &command, &params));
+    let result = session.and_then(|session| {
+        if !args.interactive {
+            let output = session.output()?;
+            print!("{}", output.stdout_string());
+            eprint!("{}", output.stderr_string());
+            return Ok(output.exit_code);
+        }
+        let (input, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in stdin().lock().lines().map_while(Result::ok) {
+                if input
+                    .send(ExecInput::Stdin(format!("{}\n", line).into_bytes()))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        session.interact(&receiver, &mut stdout(), &mut stderr())
+    });
+    match result {
+        Ok(code) => exit(code),
+        Err(e) => {
+            eprintln!("执行命令失败: {}", e);
+            exit(1);
+        }
+    }
+}
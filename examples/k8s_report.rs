@@ -0,0 +1,70 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::process::exit;
+
+use clap::Parser;
+use rust_notes::k8s::api::HttpClient;
+use rust_notes::k8s::models::HttpKubeConfig;
+use rust_notes::k8s::report::ReportFormat;
+
+// 集群资产报告, 例如: cargo run --example k8s_report -- -o yaml
+#[derive(Parser)]
+#[command(name = "k8s-report")]
+#[command(author = "tomoncle")]
+#[command(version = "1.0")]
+#[command(about = "输出 k8s 集群的节点, 各命名空间的 Pod 数量和有不可用副本的工作负载.", long_about = None)]
+struct Args {
+    /// kubeconfig 文件路径, 默认使用 KUBECONFIG 环境变量或 ~/.kube/config
+    #[arg(short, long)]
+    kubeconfig: Option<String>,
+
+    /// 使用的 context, 默认为 current-context
+    #[arg(short, long)]
+    context: Option<String>,
+
+    /// 输出格式: table, json, yaml
+    #[arg(short, long, default_value = "table")]
+    output: ReportFormat,
+}
+
+fn main() {
+    let args = Args::parse();
+    let config = match (&args.kubeconfig, &args.context) {
+        (Some(path), Some(context)) => HttpKubeConfig::read_from_context(path, context),
+        (Some(path), None) => HttpKubeConfig::read_from(path),
+        (None, context) => HttpKubeConfig::infer_context(context.as_deref()),
+    };
+    let report = config
+        .and_then(HttpClient::new)
+        .and_then(|client| client.cluster_report())
+        .and_then(|report| report.render(args.output));
+    match report {
+        Ok(report) => println!("{}", report),
+        Err(e) => {
+            eprintln!("生成集群报告失败: {}", e);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use k8s_openapi::api::authorization::v1::{
+    NonResourceAttributes, ResourceAttributes, SelfSubjectAccessReview,
+    SelfSubjectAccessReviewSpec, SubjectAccessReview, SubjectAccessReviewSpec,
+    SubjectAccessReviewStatus,
+};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::error::K8sError;
+
+const IMPERSONATE_USER: &str = "Impersonate-User";
+const IMPERSONATE_GROUP: &str = "Impersonate-Group";
+const IMPERSONATE_UID: &str = "Impersonate-Uid";
+const IMPERSONATE_EXTRA_PREFIX: &str = "Impersonate-Extra-";
+
+/// 用户身份, 用于模拟用户 ([`HttpClient::impersonate`]) 和 [`HttpClient::subject_access_review`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UserInfo {
+    pub user: String,
+    pub groups: Vec<String>,
+    pub uid: String,
+    pub extra: BTreeMap<String, Vec<String>>,
+}
+
+impl UserInfo {
+    pub fn new(user: &str) -> Self {
+        UserInfo {
+            user: user.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn group(mut self, group: &str) -> Self {
+        self.groups.push(group.to_string());
+        self
+    }
+
+    pub fn uid(mut self, uid: &str) -> Self {
+        self.uid = uid.to_string();
+        self
+    }
+
+    /// 添加一个额外属性, 同一个 `key` 可以有多个值
+    pub fn extra(mut self, key: &str, value: &str) -> Self {
+        self.extra
+            .entry(key.to_string())
+            .or_default()
+            .push(value.to_string());
+        self
+    }
+
+    /// 模拟用户的请求头, 参考 [User impersonation](https://kubernetes.io/docs/reference/access-authn-authz/authentication/#user-impersonation)
+    pub fn impersonate_headers(&self) -> Result<HeaderMap, K8sError> {
+        // apiserver 要求模拟组, uid 或额外属性时必须同时模拟用户
+        if self.user.is_empty() {
+            return Err(K8sError::Validation(
+                "impersonation requires a user name".to_string(),
+            ));
+        }
+        let mut headers = HeaderMap::new();
+        let mut append = |name: &str, value: &str| -> Result<(), K8sError> {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| K8sError::Validation(format!("invalid header {}: {}", name, e)))?;
+            let value = HeaderValue::from_str(value).map_err(|e| {
+                K8sError::Validation(format!("invalid value for header {}: {}", name, e))
+            })?;
+            headers.append(name, value);
+            Ok(())
+        };
+        append(IMPERSONATE_USER, &self.user)?;
+        for group in &self.groups {
+            append(IMPERSONATE_GROUP, group)?;
+        }
+        if !self.uid.is_empty() {
+            append(IMPERSONATE_UID, &self.uid)?;
+        }
+        for (key, values) in &self.extra {
+            let name = format!("{}{}", IMPERSONATE_EXTRA_PREFIX, escape_header_key(key));
+            for value in values {
+                append(&name, value)?;
+            }
+        }
+        Ok(headers)
+    }
+}
+
+/// 额外属性的键可能包含请求头名称不允许的字符 (例如 `/`), 与 client-go 一样使用百分号编码
+fn escape_header_key(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        let legal = byte.is_ascii_alphanumeric() || b"!#$&'*+-.^_`|~".contains(&byte);
+        if legal {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+/// 访问检查的目标, 对应 `kubectl auth can-i VERB RESOURCE`
+#[derive(Clone, Debug, PartialEq)]
+pub enum AccessCheck {
+    Resource(ResourceAttributes),
+    NonResource(NonResourceAttributes),
+}
+
+impl AccessCheck {
+    /// `resource` 的格式与 kubectl 相同: `pods`, `deployments.apps`, `pods/log`
+    pub fn new(verb: &str, resource: &str) -> Self {
+        let (resource, subresource) = match resource.split_once('/') {
+            Some((resource, subresource)) => (resource, Some(subresource.to_string())),
+            None => (resource, None),
+        };
+        let (resource, group) = resource.split_once('.').unwrap_or((resource, ""));
+        AccessCheck::Resource(ResourceAttributes {
+            verb: Some(verb.to_string()),
+            group: Some(group.to_string()),
+            resource: Some(resource.to_string()),
+            subresource,
+            ..Default::default()
+        })
+    }
+
+    /// 非资源 URL, 例如 `/healthz`, `/logs/*`
+    pub fn non_resource(verb: &str, path: &str) -> Self {
+        AccessCheck::NonResource(NonResourceAttributes {
+            verb: Some(verb.to_string()),
+            path: Some(path.to_string()),
+        })
+    }
+
+    /// 限定命名空间, 不设置时检查所有命名空间. 非资源 URL 忽略此设置
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        if let AccessCheck::Resource(attributes) = &mut self {
+            attributes.namespace = Some(namespace.to_string());
+        }
+        self
+    }
+
+    /// 限定资源名称. 非资源 URL 忽略此设置
+    pub fn name(mut self, name: &str) -> Self {
+        if let AccessCheck::Resource(attributes) = &mut self {
+            attributes.name = Some(name.to_string());
+        }
+        self
+    }
+
+    fn attributes(&self) -> (Option<ResourceAttributes>, Option<NonResourceAttributes>) {
+        match self {
+            AccessCheck::Resource(attributes) => (Some(attributes.clone()), None),
+            AccessCheck::NonResource(attributes) => (None, Some(attributes.clone())),
+        }
+    }
+}
+
+/// 访问检查的结果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccessReview {
+    pub allowed: bool,
+    /// 被显式拒绝, 而不仅是没有规则允许
+    pub denied: bool,
+    pub reason: String,
+    pub evaluation_error: String,
+}
+
+impl From<SubjectAccessReviewStatus> for AccessReview {
+    fn from(status: SubjectAccessReviewStatus) -> Self {
+        AccessReview {
+            allowed: status.allowed,
+            denied: status.denied.unwrap_or_default(),
+            reason: status.reason.unwrap_or_default(),
+            evaluation_error: status.evaluation_error.unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for AccessReview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", if self.allowed { "yes" } else { "no" })?;
+        if !self.reason.is_empty() {
+            write!(f, " - {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl HttpClient {
+    /// 以 `user` 的身份发送之后的所有请求, 当前凭证需要有 `impersonate` 权限
+    pub fn impersonate(mut self, user: UserInfo) -> Result<Self, K8sError> {
+        self.impersonation = Some(user.impersonate_headers()?);
+        Ok(self)
+    }
+
+    /// 当前用户 (或模拟的用户) 是否可以执行 `check`, 对应 `kubectl auth can-i`
+    pub fn self_access_review(&self, check: &AccessCheck) -> Result<AccessReview, K8sError> {
+        let (resource_attributes, non_resource_attributes) = check.attributes();
+        let review = SelfSubjectAccessReview {
+            spec: SelfSubjectAccessReviewSpec {
+                resource_attributes,
+                non_resource_attributes,
+            },
+            ..Default::default()
+        };
+        let review = self.create(None, &review)?;
+        Ok(review.status.map(AccessReview::from).unwrap_or_default())
+    }
+
+    /// `user` 是否可以执行 `check`, 对应 `kubectl auth can-i --as`. 需要 `subjectaccessreviews` 的 `create` 权限
+    pub fn subject_access_review(
+        &self,
+        user: &UserInfo,
+        check: &AccessCheck,
+    ) -> Result<AccessReview, K8sError> {
+        let (resource_attributes, non_resource_attributes) = check.attributes();
+        let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        let review = SubjectAccessReview {
+            spec: SubjectAccessReviewSpec {
+                user: non_empty(&user.user),
+                groups: (!user.groups.is_empty()).then(|| user.groups.clone()),
+                uid: non_empty(&user.uid),
+                extra: (!user.extra.is_empty()).then(|| user.extra.clone()),
+                resource_attributes,
+                non_resource_attributes,
+            },
+            ..Default::default()
+        };
+        let review = self.create(None, &review)?;
+        Ok(review.status.map(AccessReview::from).unwrap_or_default())
+    }
+
+    /// [`HttpClient::self_access_review`] 的简写, 只返回是否允许
+    pub fn can_i(
+        &self,
+        verb: &str,
+        resource: &str,
+        namespace: Option<&str>,
+    ) -> Result<bool, K8sError> {
+        let mut check = AccessCheck::new(verb, resource);
+        if let Some(namespace) = namespace {
+            check = check.namespace(namespace);
+        }
+        Ok(self.self_access_review(&check)?.allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn impersonate_headers_test() {
+        let user = UserInfo::new("jane")
+            .group("developers")
+            .group("system:authenticated")
+            .uid("42")
+            .extra("scopes", "view")
+            .extra("example.com/team", "a b");
+        let headers = user.impersonate_headers().unwrap();
+        assert_eq!(headers["impersonate-user"], "jane");
+        let groups: Vec<_> = headers.get_all("impersonate-group").iter().collect();
+        assert_eq!(groups, vec!["developers", "system:authenticated"]);
+        assert_eq!(headers["impersonate-uid"], "42");
+        assert_eq!(headers["impersonate-extra-scopes"], "view");
+        assert_eq!(headers["impersonate-extra-example.com%2Fteam"], "a b");
+
+        assert!(UserInfo::default()
+            .group("developers")
+            .impersonate_headers()
+            .is_err());
+    }
+
+    #[test]
+    fn access_check_test() {
+        let AccessCheck::Resource(attributes) =
+            AccessCheck::new("get", "pods/log").namespace("default")
+        else {
+            panic!("expected resource attributes");
+        };
+        assert_eq!(attributes.resource.as_deref(), Some("pods"));
+        assert_eq!(attributes.subresource.as_deref(), Some("log"));
+        assert_eq!(attributes.group.as_deref(), Some(""));
+        assert_eq!(attributes.namespace.as_deref(), Some("default"));
+
+        let AccessCheck::Resource(attributes) = AccessCheck::new("create", "deployments.apps")
+        else {
+            panic!("expected resource attributes");
+        };
+        assert_eq!(attributes.resource.as_deref(), Some("deployments"));
+        assert_eq!(attributes.group.as_deref(), Some("apps"));
+        assert_eq!(attributes.subresource, None);
+    }
+}
@@ -0,0 +1,273 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::sync::Arc;
+
+use k8s_openapi::{List, ListableResource, Resource};
+use reqwest::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Method, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::k8s::api::{build_url, configure, status_error};
+use crate::k8s::auth::Authenticator;
+use crate::k8s::error::K8sError;
+use crate::k8s::models::HttpKubeConfig;
+use crate::k8s::resource::resource_path;
+use crate::k8s::retry::{RateLimiter, RetryPolicy};
+
+/// 基于 `reqwest::Client` 的异步客户端, 可以直接在 tokio 任务和 actix 的 handler 中使用.
+///
+/// 与 [`HttpClient`][crate::k8s::api::HttpClient] 共用 [`HttpKubeConfig`], URL 拼接和资源路径的计算.
+/// 读取 token 文件和执行 exec 插件在 `spawn_blocking` 的线程中进行, 不会阻塞运行时.
+pub struct AsyncHttpClient {
+    server: String,
+    namespace: String,
+    pub(crate) client: reqwest::Client,
+    auth: Arc<Authenticator>,
+    /// 同一时间只有一个任务刷新凭证
+    refresh: Mutex<()>,
+    pub(crate) rate_limiter: Option<RateLimiter>,
+    pub(crate) retry_policy: RetryPolicy,
+}
+
+impl AsyncHttpClient {
+    pub fn new(http_config: HttpKubeConfig) -> Result<Self, K8sError> {
+        let builder = configure(reqwest::Client::builder(), &http_config)?;
+        Ok(AsyncHttpClient {
+            server: http_config.server,
+            namespace: http_config.namespace,
+            client: builder.build().map_err(|e| K8sError::Tls(e.to_string()))?,
+            auth: Arc::new(Authenticator::new(http_config.auth)),
+            refresh: Mutex::new(()),
+            rate_limiter: None,
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+
+    /// 创建请求, 并注入 `Authorization` 请求头
+    pub async fn request(&self, method: Method, url: &str) -> Result<RequestBuilder, K8sError> {
+        let mut request = self.client.request(method, url);
+        if let Some(value) = self.auth_header().await? {
+            let mut value =
+                HeaderValue::from_str(&value).map_err(|e| K8sError::Auth(e.to_string()))?;
+            value.set_sensitive(true);
+            request = request.header(AUTHORIZATION, value);
+        }
+        Ok(request)
+    }
+
+    async fn auth_header(&self) -> Result<Option<String>, K8sError> {
+        if let Some(header) = self.auth.cached_header() {
+            return Ok(header);
+        }
+        let _refresh = self.refresh.lock().await;
+        // 等待期间其他任务可能已经刷新
+        if let Some(header) = self.auth.cached_header() {
+            return Ok(header);
+        }
+        let auth = self.auth.clone();
+        tokio::task::spawn_blocking(move || auth.header())
+            .await
+            .map_err(|e| K8sError::Auth(format!("refresh credentials: {}", e)))?
+    }
+
+    /// 经过限流和重试发送请求, 非 2xx 响应时返回 apiserver 的 `Status` 错误信息
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, K8sError> {
+        Self::check(self.execute(request).await?).await
+    }
+
+    /// 检查响应状态码, 非 2xx 响应时返回 apiserver 的 `Status` 错误信息
+    pub async fn check(response: Response) -> Result<Response, K8sError> {
+        let code = response.status();
+        if code.is_success() {
+            return Ok(response);
+        }
+        Err(status_error(
+            code,
+            response.text().await.unwrap_or_default(),
+        ))
+    }
+
+    /// context 的默认命名空间
+    pub fn default_namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub async fn healthy(&self) -> Result<bool, K8sError> {
+        let request = self.request(Method::GET, &self.server).await?;
+        let response = self.execute(request).await?;
+        Ok(response.status().is_success())
+    }
+
+    pub async fn apis(&self) -> Result<String, K8sError> {
+        let request = self.request(Method::GET, &self.server).await?;
+        let json: serde_json::Value = self.send(request).await?.json().await?;
+        Ok(json.to_string())
+    }
+
+    /// 查询资源列表, 命名空间资源在 `namespace` 为 `None` 时查询所有命名空间
+    pub async fn list<K>(&self, namespace: Option<&str>, args: &[&str]) -> Result<List<K>, K8sError>
+    where
+        K: Resource + ListableResource + DeserializeOwned,
+        K::Scope: 'static,
+    {
+        let url = self.url(&[&resource_path::<K>(namespace, None)], args);
+        let request = self.request(Method::GET, &url).await?;
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    /// 查询单个资源, `namespace` 为 `None` 时使用 context 的默认命名空间
+    pub async fn get<K>(&self, namespace: Option<&str>, name: &str) -> Result<K, K8sError>
+    where
+        K: Resource + DeserializeOwned,
+        K::Scope: 'static,
+    {
+        let url = self.object_url::<K>(namespace, Some(name));
+        let request = self.request(Method::GET, &url).await?;
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    /// 创建资源, `namespace` 为 `None` 时使用 context 的默认命名空间
+    pub async fn create<K>(&self, namespace: Option<&str>, object: &K) -> Result<K, K8sError>
+    where
+        K: Resource + Serialize + DeserializeOwned,
+        K::Scope: 'static,
+    {
+        let url = self.object_url::<K>(namespace, None);
+        let request = Self::json_body(self.request(Method::POST, &url).await?, object)?;
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    /// 替换资源, `object` 需要携带 `metadata.resourceVersion` 用于冲突检测
+    pub async fn replace<K>(
+        &self,
+        namespace: Option<&str>,
+        name: &str,
+        object: &K,
+    ) -> Result<K, K8sError>
+    where
+        K: Resource + Serialize + DeserializeOwned,
+        K::Scope: 'static,
+    {
+        let url = self.object_url::<K>(namespace, Some(name));
+        let request = Self::json_body(self.request(Method::PUT, &url).await?, object)?;
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    /// 删除资源, `namespace` 为 `None` 时使用 context 的默认命名空间
+    pub async fn delete<K>(&self, namespace: Option<&str>, name: &str) -> Result<(), K8sError>
+    where
+        K: Resource,
+        K::Scope: 'static,
+    {
+        let url = self.object_url::<K>(namespace, Some(name));
+        self.send(self.request(Method::DELETE, &url).await?).await?;
+        Ok(())
+    }
+
+    pub fn url(&self, paths: &[&str], args: &[&str]) -> String {
+        build_url(&self.server, paths, args)
+    }
+
+    fn object_url<K>(&self, namespace: Option<&str>, name: Option<&str>) -> String
+    where
+        K: Resource,
+        K::Scope: 'static,
+    {
+        let namespace = namespace.unwrap_or(&self.namespace);
+        self.url(&[&resource_path::<K>(Some(namespace), name)], &[])
+    }
+
+    fn json_body<T: Serialize>(
+        request: RequestBuilder,
+        object: &T,
+    ) -> Result<RequestBuilder, K8sError> {
+        Ok(request
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(object)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use k8s_openapi::api::core::v1::Pod;
+
+    use crate::k8s::models::{ExecAuth, HttpAuth};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn inside_runtime_test() {
+        // 阻塞客户端在 tokio 运行时中使用会 panic, 异步客户端返回错误即可
+        let client = AsyncHttpClient::new(HttpKubeConfig {
+            server: "http://127.0.0.1:1".to_string(),
+            namespace: "default".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            client.url(&["/api/v1/pods"], &["limit=500"]),
+            "http://127.0.0.1:1/api/v1/pods?limit=500"
+        );
+        assert!(client.get::<Pod>(None, "nginx").await.is_err());
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn exec_auth_not_blocking_test() {
+        let credential = r#"{"apiVersion":"client.authentication.k8s.io/v1beta1","kind":"ExecCredential","status":{"token":"exec-token"}}"#;
+        let client = AsyncHttpClient::new(HttpKubeConfig {
+            server: "http://127.0.0.1:1".to_string(),
+            auth: HttpAuth::Exec(ExecAuth {
+                api_version: "client.authentication.k8s.io/v1beta1".to_string(),
+                command: "sh".to_string(),
+                args: vec![
+                    "-c".to_string(),
+                    "sleep 0.5; echo \"$CREDENTIAL\"".to_string(),
+                ],
+                env: vec![("CREDENTIAL".to_string(), credential.to_string())],
+                cluster: None,
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        // 单线程运行时中, 插件执行期间其他任务仍然可以运行
+        let start = Instant::now();
+        let ticker = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            start.elapsed()
+        });
+        let header = client.auth_header().await.unwrap();
+        assert_eq!(header.as_deref(), Some("Bearer exec-token"));
+        assert!(ticker.await.unwrap() < Duration::from_millis(400));
+        assert_eq!(
+            client.auth.cached_header(),
+            Some(Some("Bearer exec-token".to_string()))
+        );
+    }
+}
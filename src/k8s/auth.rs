@@ -0,0 +1,257 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::process::Command;
+use std::sync::Mutex;
+
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+
+use crate::k8s::error::K8sError;
+use crate::k8s::models::{ExecAuth, HttpAuth};
+
+/// token 文件的重新读取间隔, projected service account token 会定期轮换
+const TOKEN_FILE_REFRESH_SECONDS: i64 = 60;
+
+/// 根据 [`HttpAuth`] 生成 `Authorization` 请求头,
+/// `exec` 插件返回的 token 会被缓存到 `expirationTimestamp` 为止,
+/// `tokenFile` 每隔 [`TOKEN_FILE_REFRESH_SECONDS`] 秒重新读取一次.
+pub struct Authenticator {
+    auth: HttpAuth,
+    cache: Mutex<Option<CachedToken>>,
+}
+
+struct CachedToken {
+    token: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl CachedToken {
+    fn is_valid(&self) -> bool {
+        // 提前 10 秒过期, 避免请求发出时 token 恰好失效
+        self.expires_at
+            .map(|expires_at| Utc::now() + Duration::seconds(10) < expires_at)
+            .unwrap_or(true)
+    }
+}
+
+/// `exec` 插件输出的 ExecCredential
+#[derive(Debug, Deserialize)]
+struct ExecCredential {
+    status: Option<ExecCredentialStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExecCredentialStatus {
+    token: Option<String>,
+    expiration_timestamp: Option<String>,
+    client_certificate_data: Option<String>,
+    client_key_data: Option<String>,
+}
+
+impl Authenticator {
+    pub fn new(auth: HttpAuth) -> Self {
+        Authenticator {
+            auth,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// 返回 `Authorization` 请求头的值, 不需要认证头时返回 `None`
+    pub fn header(&self) -> Result<Option<String>, K8sError> {
+        match &self.auth {
+            HttpAuth::None => Ok(None),
+            HttpAuth::Token(token) => Ok(Some(format!("Bearer {}", token))),
+            HttpAuth::TokenFile(path) => self.cached(|| {
+                let token = std::fs::read_to_string(path)
+                    .map_err(|e| K8sError::Auth(format!("read token file '{}': {}", path, e)))?;
+                Ok(CachedToken {
+                    token: token.trim().to_string(),
+                    expires_at: Some(Utc::now() + Duration::seconds(TOKEN_FILE_REFRESH_SECONDS)),
+                })
+            }),
+            HttpAuth::Basic { username, password } => {
+                let credentials = base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", username, password));
+                Ok(Some(format!("Basic {}", credentials)))
+            }
+            HttpAuth::Exec(exec) => self.cached(|| run_exec_plugin(exec)),
+        }
+    }
+
+    /// 不需要读取文件或执行插件时返回请求头, 缓存失效或正在刷新时返回 `None`.
+    /// 供异步客户端在运行时线程上调用, 不会阻塞
+    pub(crate) fn cached_header(&self) -> Option<Option<String>> {
+        match &self.auth {
+            HttpAuth::TokenFile(_) | HttpAuth::Exec(_) => {
+                let cache = self.cache.try_lock().ok()?;
+                cache
+                    .as_ref()
+                    .filter(|cached| cached.is_valid())
+                    .map(|cached| Some(format!("Bearer {}", cached.token)))
+            }
+            _ => self.header().ok(),
+        }
+    }
+
+    fn cached<F>(&self, fetch: F) -> Result<Option<String>, K8sError>
+    where
+        F: FnOnce() -> Result<CachedToken, K8sError>,
+    {
+        let mut cache = self.cache.lock().unwrap();
+        match cache.as_ref() {
+            Some(cached) if cached.is_valid() => {}
+            _ => *cache = Some(fetch()?),
+        }
+        Ok(cache
+            .as_ref()
+            .map(|cached| format!("Bearer {}", cached.token)))
+    }
+}
+
+/// 执行凭证插件, 从标准输出中解析 ExecCredential
+fn run_exec_plugin(exec: &ExecAuth) -> Result<CachedToken, K8sError> {
+    let mut spec = serde_json::json!({ "interactive": false });
+    if let Some(cluster) = &exec.cluster {
+        spec["cluster"] = cluster.clone();
+    }
+    let exec_info = serde_json::json!({
+        "apiVersion": exec.api_version,
+        "kind": "ExecCredential",
+        "spec": spec,
+    });
+    let output = Command::new(&exec.command)
+        .args(&exec.args)
+        .envs(exec.env.iter().map(|(k, v)| (k, v)))
+        .env("KUBERNETES_EXEC_INFO", exec_info.to_string())
+        .output()
+        .map_err(|e| K8sError::Auth(format!("run exec plugin '{}': {}", exec.command, e)))?;
+    if !output.status.success() {
+        return Err(K8sError::Auth(format!(
+            "exec plugin '{}' exited with {}: {}",
+            exec.command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let credential: ExecCredential = serde_json::from_slice(&output.stdout).map_err(|e| {
+        K8sError::Auth(format!(
+            "parse ExecCredential from '{}': {}",
+            exec.command, e
+        ))
+    })?;
+    let status = credential.status.ok_or_else(|| {
+        K8sError::Auth(format!("exec plugin '{}' returned no status", exec.command))
+    })?;
+    // 客户端证书在创建 HttpClient 时加载, 不能按插件的输出轮换
+    if status.client_certificate_data.is_some() || status.client_key_data.is_some() {
+        return Err(K8sError::Auth(format!(
+            "exec plugin '{}' returned clientCertificateData/clientKeyData, \
+             client certificate credentials from exec plugins are not supported",
+            exec.command
+        )));
+    }
+    let token = status.token.ok_or_else(|| {
+        K8sError::Auth(format!("exec plugin '{}' returned no token", exec.command))
+    })?;
+    let expires_at = match status.expiration_timestamp {
+        Some(timestamp) => Some(
+            DateTime::parse_from_rfc3339(&timestamp)
+                .map_err(|e| K8sError::Auth(format!("invalid expirationTimestamp: {}", e)))?
+                .with_timezone(&Utc),
+        ),
+        None => None,
+    };
+    Ok(CachedToken { token, expires_at })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic_auth_test() {
+        let auth = Authenticator::new(HttpAuth::Basic {
+            username: "admin".to_string(),
+            password: "secret".to_string(),
+        });
+        assert_eq!(auth.header().unwrap().unwrap(), "Basic YWRtaW46c2VjcmV0");
+    }
+
+    #[test]
+    fn token_auth_test() {
+        let auth = Authenticator::new(HttpAuth::Token("abc".to_string()));
+        assert_eq!(auth.header().unwrap().unwrap(), "Bearer abc");
+        assert_eq!(Authenticator::new(HttpAuth::None).header().unwrap(), None);
+    }
+
+    #[test]
+    fn token_file_refresh_test() {
+        let path = std::env::temp_dir().join(format!("rust-notes-token-{}", std::process::id()));
+        std::fs::write(&path, "first\n").unwrap();
+        let auth = Authenticator::new(HttpAuth::TokenFile(path.display().to_string()));
+        assert_eq!(auth.header().unwrap().unwrap(), "Bearer first");
+
+        // 文件轮换后, 缓存未过期前仍使用旧 token
+        std::fs::write(&path, "second\n").unwrap();
+        assert_eq!(auth.header().unwrap().unwrap(), "Bearer first");
+
+        auth.cache.lock().unwrap().as_mut().unwrap().expires_at = Some(Utc::now());
+        assert_eq!(auth.header().unwrap().unwrap(), "Bearer second");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn exec_auth_test() {
+        let credential = r#"{"apiVersion":"client.authentication.k8s.io/v1beta1","kind":"ExecCredential","status":{"token":"exec-token","expirationTimestamp":"2099-01-01T00:00:00Z"}}"#;
+        let auth = Authenticator::new(HttpAuth::Exec(ExecAuth {
+            api_version: "client.authentication.k8s.io/v1beta1".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), "echo \"$CREDENTIAL\"".to_string()],
+            env: vec![("CREDENTIAL".to_string(), credential.to_string())],
+            cluster: None,
+        }));
+        assert_eq!(auth.header().unwrap().unwrap(), "Bearer exec-token");
+        assert!(auth.cache.lock().unwrap().as_ref().unwrap().is_valid());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn exec_client_certificate_test() {
+        let credential = r#"{"apiVersion":"client.authentication.k8s.io/v1beta1","kind":"ExecCredential","status":{"clientCertificateData":"cert","clientKeyData":"key"}}"#;
+        let auth = Authenticator::new(HttpAuth::Exec(ExecAuth {
+            api_version: "client.authentication.k8s.io/v1beta1".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), "echo \"$CREDENTIAL\"".to_string()],
+            env: vec![("CREDENTIAL".to_string(), credential.to_string())],
+            cluster: None,
+        }));
+        let error = auth.header().unwrap_err();
+        assert!(matches!(error, K8sError::Auth(_)));
+        assert!(error.to_string().contains("not supported"), "{}", error);
+    }
+}
@@ -0,0 +1,512 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use kube::core::{DynamicObject, TypeMeta};
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::discovery::DiscoveredResource;
+use crate::k8s::error::K8sError;
+use crate::k8s::manifests::{
+    apply_object, read_manifests, sort_manifests, ApplyOptions, ApplyResult,
+};
+
+/// 未指定资源类型时不备份的资源: 由控制器生成或只有短期意义的对象
+const SKIPPED_KINDS: &[&str] = &["Event", "Endpoints", "EndpointSlice", "Lease"];
+
+/// 由 apiserver 维护的元数据字段, 备份时删除
+const SERVER_METADATA: &[&str] = &[
+    "managedFields",
+    "resourceVersion",
+    "uid",
+    "selfLink",
+    "creationTimestamp",
+    "generation",
+    "deletionTimestamp",
+    "deletionGracePeriodSeconds",
+    "ownerReferences",
+];
+
+/// 由客户端或控制器写入的注解, 恢复后会重新生成
+const SERVER_ANNOTATIONS: &[&str] = &[
+    "kubectl.kubernetes.io/last-applied-configuration",
+    "deployment.kubernetes.io/revision",
+    "pv.kubernetes.io/bind-completed",
+    "pv.kubernetes.io/bound-by-controller",
+];
+
+/// 每个命名空间中由控制器自动创建的 ConfigMap
+const GENERATED_CONFIG_MAPS: &[&str] = &["kube-root-ca.crt"];
+
+/// [`restore_namespace`] 的参数
+#[derive(Clone, Debug, Default)]
+pub struct RestoreOptions {
+    /// 恢复到的命名空间, 为 `None` 时恢复到备份时的命名空间
+    pub namespace: Option<String>,
+
+    /// Server-Side Apply 的参数, 其中的 `namespace` 不生效
+    pub apply: ApplyOptions,
+}
+
+/// 将命名空间中指定类型的资源导出为 YAML 文件, 每个对象一个文件, 路径为 `dir/{plural}[.{group}]/{name}.yaml`,
+/// 命名空间对象本身保存在 `dir/namespaces/{namespace}.yaml`. 返回写入的文件.
+///
+/// `kinds` 可以是 kind 或复数名称 (例如 `Deployment`, `deployments`, `deployments.apps`), 不区分大小写.
+/// 为空时备份所有支持 list 和 create 的命名空间资源, 但跳过 Event, Endpoints 等自动生成的资源.
+/// 由控制器创建的对象 (例如 Deployment 的 ReplicaSet 和 Pod) 和 ServiceAccount token Secret 不会被备份.
+///
+/// 删除 `status`, `managedFields`, `resourceVersion`, `uid` 等由服务端维护的字段, 以及 Service 的 `clusterIP`.
+/// 备份中可能包含 Secret, unix 下文件的权限为 0600.
+pub fn backup_namespace<P: AsRef<Path>>(
+    client: &HttpClient,
+    namespace: &str,
+    kinds: &[&str],
+    dir: P,
+) -> Result<Vec<PathBuf>, K8sError> {
+    let dir = dir.as_ref();
+    let mut files = vec![];
+    let namespace_object = client.get_dynamic("v1", "Namespace", None, namespace)?;
+    files.push(write_object(dir, &namespace_resource(), namespace_object)?);
+
+    for resource in backup_resources(client, kinds)? {
+        let list = client.list_dynamic(
+            &resource.api_version(),
+            &resource.kind,
+            Some(namespace),
+            &[],
+        )?;
+        for mut object in list.items {
+            // 列表中的对象没有 apiVersion 和 kind
+            object.types = Some(TypeMeta {
+                api_version: resource.api_version(),
+                kind: resource.kind.clone(),
+            });
+            if is_generated(&object) {
+                continue;
+            }
+            files.push(write_object(dir, &resource, object)?);
+        }
+    }
+    Ok(files)
+}
+
+/// 按依赖顺序恢复 [`backup_namespace`] 导出的目录, 使用 Server-Side Apply, 已存在的对象会被更新.
+///
+/// 恢复到其他命名空间时, 同时修改命名空间对象的名称和 RoleBinding 中指向原命名空间的 ServiceAccount.
+pub fn restore_namespace<P: AsRef<Path>>(
+    client: &HttpClient,
+    dir: P,
+    options: &RestoreOptions,
+) -> Result<Vec<ApplyResult>, K8sError> {
+    let dir = dir.as_ref();
+    let mut objects = vec![];
+    let mut dirs: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| K8sError::Config(format!("read {}: {}", dir.display(), e)))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+    for path in dirs {
+        objects.extend(read_manifests(path)?);
+    }
+    sort_manifests(&mut objects);
+
+    if let Some(target) = &options.namespace {
+        let source = objects
+            .iter()
+            .find(|object| kind_of(object) == "Namespace")
+            .and_then(|object| object.metadata.name.clone());
+        for object in &mut objects {
+            retarget(object, source.as_deref(), target);
+        }
+    }
+    let mut results = vec![];
+    for object in objects {
+        results.push(apply_object(client, object, &options.apply)?);
+    }
+    Ok(results)
+}
+
+/// 需要备份的资源, 同一个 group 和 kind 有多个版本时只使用最稳定的最新版本
+fn backup_resources(
+    client: &HttpClient,
+    kinds: &[&str],
+) -> Result<Vec<DiscoveredResource>, K8sError> {
+    let discovery = client.discovery()?;
+    let mut selected: HashMap<(String, String), DiscoveredResource> = HashMap::new();
+    for resource in discovery.resources() {
+        if !resource.namespaced || !resource.supports("list") || !resource.supports("create") {
+            continue;
+        }
+        let wanted = if kinds.is_empty() {
+            !SKIPPED_KINDS.contains(&resource.kind.as_str())
+        } else {
+            kinds.iter().any(|kind| matches_kind(resource, kind))
+        };
+        if !wanted {
+            continue;
+        }
+        let key = (resource.group.clone(), resource.kind.clone());
+        let newer = match selected.get(&key) {
+            Some(current) => {
+                version_priority(&resource.version) > version_priority(&current.version)
+            }
+            None => true,
+        };
+        if newer {
+            selected.insert(key, resource.clone());
+        }
+    }
+    let mut resources: Vec<DiscoveredResource> = selected.into_values().collect();
+    resources.sort_by(|a, b| (&a.group, &a.plural).cmp(&(&b.group, &b.plural)));
+    Ok(resources)
+}
+
+fn matches_kind(resource: &DiscoveredResource, kind: &str) -> bool {
+    resource.kind.eq_ignore_ascii_case(kind)
+        || resource.plural.eq_ignore_ascii_case(kind)
+        || directory_name(resource).eq_ignore_ascii_case(kind)
+}
+
+/// 版本的优先级: GA > beta > alpha, 同一稳定级别中版本号大的优先, 例如 `v2` > `v1` > `v2beta1`
+fn version_priority(version: &str) -> (u8, u32, u32) {
+    let version = version.trim_start_matches('v');
+    let digits = |s: &str| -> u32 {
+        s.chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>()
+            .parse()
+            .unwrap_or(0)
+    };
+    for (stability, label) in [(1, "beta"), (0, "alpha")] {
+        if let Some((major, minor)) = version.split_once(label) {
+            return (stability, digits(major), digits(minor));
+        }
+    }
+    (2, digits(version), 0)
+}
+
+/// 由控制器创建或维护的对象, 恢复后会自动重新生成
+fn is_generated(object: &DynamicObject) -> bool {
+    let owned = object
+        .metadata
+        .owner_references
+        .iter()
+        .flatten()
+        .any(|owner| owner.controller == Some(true));
+    let name = object.metadata.name.as_deref().unwrap_or_default();
+    owned
+        || object.metadata.deletion_timestamp.is_some()
+        || match kind_of(object) {
+            "ConfigMap" => GENERATED_CONFIG_MAPS.contains(&name),
+            "Secret" => object.data["type"] == "kubernetes.io/service-account-token",
+            _ => false,
+        }
+}
+
+/// 删除由服务端维护或分配的字段 (状态, Service 的 IP 和节点端口, PVC 绑定的 PV)
+fn strip(object: &mut DynamicObject) {
+    let mut metadata = serde_json::to_value(&object.metadata).unwrap_or_default();
+    if let Some(fields) = metadata.as_object_mut() {
+        for field in SERVER_METADATA {
+            fields.remove(*field);
+        }
+        if let Some(annotations) = fields
+            .get_mut("annotations")
+            .and_then(|a| a.as_object_mut())
+        {
+            for annotation in SERVER_ANNOTATIONS {
+                annotations.remove(*annotation);
+            }
+            if annotations.is_empty() {
+                fields.remove("annotations");
+            }
+        }
+    }
+    object.metadata = serde_json::from_value(metadata).unwrap_or_default();
+    if let Some(data) = object.data.as_object_mut() {
+        data.remove("status");
+    }
+    let kind = kind_of(object).to_string();
+    let Some(spec) = object.data["spec"].as_object_mut() else {
+        return;
+    };
+    match kind.as_str() {
+        "Service" => {
+            // clusterIP 由 apiserver 分配, headless Service 的 `None` 需要保留
+            if spec.get("clusterIP").and_then(|ip| ip.as_str()) != Some("None") {
+                spec.remove("clusterIP");
+                spec.remove("clusterIPs");
+            }
+            // 节点端口也是分配的, 恢复到其他集群时可能冲突
+            spec.remove("healthCheckNodePort");
+            for port in spec
+                .get_mut("ports")
+                .and_then(|ports| ports.as_array_mut())
+                .into_iter()
+                .flatten()
+            {
+                if let Some(port) = port.as_object_mut() {
+                    port.remove("nodePort");
+                }
+            }
+        }
+        // 绑定的 PV 不会随备份恢复, 由控制器重新绑定
+        "PersistentVolumeClaim" => {
+            spec.remove("volumeName");
+        }
+        _ => {}
+    }
+}
+
+/// 修改对象的命名空间, `source` 为备份时的命名空间
+fn retarget(object: &mut DynamicObject, source: Option<&str>, target: &str) {
+    if kind_of(object) == "Namespace" {
+        object.metadata.name = Some(target.to_string());
+        return;
+    }
+    if object.metadata.namespace.is_some() {
+        object.metadata.namespace = Some(target.to_string());
+    }
+    if kind_of(object) == "RoleBinding" {
+        for subject in object.data["subjects"].as_array_mut().into_iter().flatten() {
+            if subject["kind"] == "ServiceAccount" && subject["namespace"].as_str() == source {
+                subject["namespace"] = target.into();
+            }
+        }
+    }
+}
+
+fn write_object(
+    dir: &Path,
+    resource: &DiscoveredResource,
+    mut object: DynamicObject,
+) -> Result<PathBuf, K8sError> {
+    strip(&mut object);
+    let name = object.metadata.name.clone().unwrap_or_default();
+    let path = dir
+        .join(directory_name(resource))
+        .join(format!("{}.yaml", name));
+    let io_error = |e: std::io::Error| K8sError::Config(format!("write {}: {}", path.display(), e));
+    std::fs::create_dir_all(path.parent().unwrap_or(dir)).map_err(io_error)?;
+    let yaml = serde_yaml::to_string(&object).map_err(|e| K8sError::Decode(e.to_string()))?;
+    std::fs::write(&path, yaml).map_err(io_error)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+            .map_err(io_error)?;
+    }
+    Ok(path)
+}
+
+/// `{plural}.{group}`, core 组为 `{plural}`, 与 kubectl 的资源名称相同
+fn directory_name(resource: &DiscoveredResource) -> String {
+    if resource.group.is_empty() {
+        resource.plural.clone()
+    } else {
+        format!("{}.{}", resource.plural, resource.group)
+    }
+}
+
+fn namespace_resource() -> DiscoveredResource {
+    DiscoveredResource {
+        group: String::new(),
+        version: "v1".to_string(),
+        kind: "Namespace".to_string(),
+        plural: "namespaces".to_string(),
+        namespaced: false,
+        verbs: vec![],
+    }
+}
+
+fn kind_of(object: &DynamicObject) -> &str {
+    object
+        .types
+        .as_ref()
+        .map(|types| types.kind.as_str())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(yaml: &str) -> DynamicObject {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn strip_server_fields() {
+        let mut service = object(
+            r###"
+apiVersion: v1
+kind: Service
+metadata:
+  name: nginx
+  namespace: demo
+  uid: 6f1c
+  resourceVersion: "1000"
+  creationTimestamp: "2024-01-02T03:04:05Z"
+  managedFields:
+  - manager: kubectl
+  annotations:
+    kubectl.kubernetes.io/last-applied-configuration: "{}"
+  labels:
+    app: nginx
+spec:
+  type: LoadBalancer
+  clusterIP: 10.96.0.10
+  clusterIPs: [10.96.0.10]
+  externalTrafficPolicy: Local
+  healthCheckNodePort: 32000
+  ports:
+  - port: 80
+    nodePort: 31080
+status:
+  loadBalancer: {}
+"###,
+        );
+        strip(&mut service);
+        assert_eq!(
+            serde_json::to_value(&service).unwrap(),
+            serde_json::to_value(object(
+                r###"
+apiVersion: v1
+kind: Service
+metadata:
+  name: nginx
+  namespace: demo
+  labels:
+    app: nginx
+spec:
+  type: LoadBalancer
+  externalTrafficPolicy: Local
+  ports:
+  - port: 80
+"###
+            ))
+            .unwrap()
+        );
+
+        let mut claim = object(
+            r###"
+apiVersion: v1
+kind: PersistentVolumeClaim
+metadata:
+  name: data
+  annotations:
+    pv.kubernetes.io/bind-completed: "yes"
+    pv.kubernetes.io/bound-by-controller: "yes"
+    volume.kubernetes.io/storage-provisioner: rancher.io/local-path
+spec:
+  accessModes: [ReadWriteOnce]
+  volumeName: pvc-6f1c
+status:
+  phase: Bound
+"###,
+        );
+        strip(&mut claim);
+        assert_eq!(
+            serde_json::to_value(&claim).unwrap(),
+            serde_json::to_value(object(
+                r###"
+apiVersion: v1
+kind: PersistentVolumeClaim
+metadata:
+  name: data
+  annotations:
+    volume.kubernetes.io/storage-provisioner: rancher.io/local-path
+spec:
+  accessModes: [ReadWriteOnce]
+"###
+            ))
+            .unwrap()
+        );
+
+        let mut headless = object(
+            "apiVersion: v1\nkind: Service\nmetadata:\n  name: db\nspec:\n  clusterIP: None\n",
+        );
+        strip(&mut headless);
+        assert_eq!(headless.data["spec"]["clusterIP"], "None");
+    }
+
+    #[test]
+    fn skip_generated_objects() {
+        let owned = object(
+            "apiVersion: v1\nkind: Pod\nmetadata:\n  name: nginx-0\n  ownerReferences:\n  - apiVersion: apps/v1\n    kind: ReplicaSet\n    name: nginx\n    uid: 6f1c\n    controller: true\n",
+        );
+        assert!(is_generated(&owned));
+        let token = object(
+            "apiVersion: v1\nkind: Secret\nmetadata:\n  name: default-token\ntype: kubernetes.io/service-account-token\n",
+        );
+        assert!(is_generated(&token));
+        let secret = object("apiVersion: v1\nkind: Secret\nmetadata:\n  name: tls\ntype: Opaque\n");
+        assert!(!is_generated(&secret));
+    }
+
+    #[test]
+    fn version_order() {
+        let mut versions = vec!["v1beta1", "v2", "v1alpha1", "v1", "v2beta2", "v1beta2"];
+        versions.sort_by_key(|version| version_priority(version));
+        assert_eq!(
+            versions,
+            vec!["v1alpha1", "v1beta1", "v1beta2", "v2beta2", "v1", "v2"]
+        );
+    }
+
+    #[test]
+    fn retarget_namespace() {
+        let mut binding = object(
+            r###"
+apiVersion: rbac.authorization.k8s.io/v1
+kind: RoleBinding
+metadata:
+  name: reader
+  namespace: demo
+roleRef:
+  apiGroup: rbac.authorization.k8s.io
+  kind: Role
+  name: reader
+subjects:
+- kind: ServiceAccount
+  name: default
+  namespace: demo
+- kind: ServiceAccount
+  name: prometheus
+  namespace: monitoring
+"###,
+        );
+        retarget(&mut binding, Some("demo"), "demo-copy");
+        assert_eq!(binding.metadata.namespace.as_deref(), Some("demo-copy"));
+        assert_eq!(binding.data["subjects"][0]["namespace"], "demo-copy");
+        assert_eq!(binding.data["subjects"][1]["namespace"], "monitoring");
+
+        let mut namespace = object("apiVersion: v1\nkind: Namespace\nmetadata:\n  name: demo\n");
+        retarget(&mut namespace, Some("demo"), "demo-copy");
+        assert_eq!(namespace.metadata.name.as_deref(), Some("demo-copy"));
+    }
+}
@@ -0,0 +1,392 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::hash::Hash;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::{ListableResource, Metadata, Resource};
+use serde::de::DeserializeOwned;
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::informer::{Informer, ObjectKey, Store};
+
+/// 去重的工作队列, 语义与 client-go 的 workqueue 相同:
+///
+/// - 已在队列中的元素不会重复加入
+/// - 正在处理的元素再次加入时, 在 [`WorkQueue::done`] 之后重新入队, 同一个元素不会被并发处理
+/// - 支持延迟加入和按失败次数指数退避
+pub struct WorkQueue<T> {
+    state: Mutex<QueueState<T>>,
+    ready: Condvar,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+struct QueueState<T> {
+    queue: VecDeque<T>,
+    queued: HashSet<T>,
+    processing: HashSet<T>,
+    dirty: HashSet<T>,
+    delayed: Vec<(Instant, T)>,
+    failures: HashMap<T, u32>,
+    shutdown: bool,
+}
+
+impl<T: Clone + Eq + Hash> WorkQueue<T> {
+    /// `initial_backoff` 和 `max_backoff` 用于 [`WorkQueue::add_rate_limited`]
+    pub fn new(initial_backoff: Duration, max_backoff: Duration) -> Self {
+        WorkQueue {
+            state: Mutex::new(QueueState {
+                queue: VecDeque::new(),
+                queued: HashSet::new(),
+                processing: HashSet::new(),
+                dirty: HashSet::new(),
+                delayed: vec![],
+                failures: HashMap::new(),
+                shutdown: false,
+            }),
+            ready: Condvar::new(),
+            initial_backoff,
+            max_backoff,
+        }
+    }
+
+    pub fn add(&self, item: T) {
+        let mut state = self.state.lock().unwrap();
+        state.push(item);
+        self.ready.notify_one();
+    }
+
+    /// 等待 `delay` 后加入队列
+    pub fn add_after(&self, item: T, delay: Duration) {
+        if delay.is_zero() {
+            return self.add(item);
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.shutdown {
+            return;
+        }
+        state.delayed.push((Instant::now() + delay, item));
+        // 唤醒等待中的线程重新计算超时时间
+        self.ready.notify_all();
+    }
+
+    /// 按失败次数指数退避后重新加入队列, 成功处理后调用 [`WorkQueue::forget`] 重置
+    pub fn add_rate_limited(&self, item: T) {
+        let failures = {
+            let mut state = self.state.lock().unwrap();
+            let failures = state.failures.entry(item.clone()).or_insert(0);
+            *failures += 1;
+            *failures
+        };
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(failures - 1))
+            .min(self.max_backoff);
+        self.add_after(item, backoff);
+    }
+
+    pub fn forget(&self, item: &T) {
+        self.state.lock().unwrap().failures.remove(item);
+    }
+
+    /// 失败的次数
+    pub fn failures(&self, item: &T) -> u32 {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .get(item)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// 阻塞获取下一个元素, 队列关闭后返回 `None`. 处理完成后必须调用 [`WorkQueue::done`]
+    pub fn get(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.shutdown {
+                return None;
+            }
+            let now = Instant::now();
+            state.promote(now);
+            if let Some(item) = state.queue.pop_front() {
+                state.queued.remove(&item);
+                state.processing.insert(item.clone());
+                return Some(item);
+            }
+            state = match state.delayed.iter().map(|(at, _)| *at).min() {
+                Some(at) => self.ready.wait_timeout(state, at - now).unwrap().0,
+                None => self.ready.wait(state).unwrap(),
+            };
+        }
+    }
+
+    pub fn done(&self, item: &T) {
+        let mut state = self.state.lock().unwrap();
+        state.processing.remove(item);
+        if state.dirty.remove(item) {
+            state.push(item.clone());
+            self.ready.notify_one();
+        }
+    }
+
+    /// 关闭队列, 所有 [`WorkQueue::get`] 返回 `None`
+    pub fn shutdown(&self) {
+        self.state.lock().unwrap().shutdown = true;
+        self.ready.notify_all();
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.state.lock().unwrap().shutdown
+    }
+
+    /// 等待处理的元素数量, 不包括延迟加入的元素
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Clone + Eq + Hash> QueueState<T> {
+    fn push(&mut self, item: T) {
+        if self.shutdown || self.queued.contains(&item) {
+            return;
+        }
+        if self.processing.contains(&item) {
+            self.dirty.insert(item);
+            return;
+        }
+        self.queued.insert(item.clone());
+        self.queue.push_back(item);
+    }
+
+    /// 将到期的延迟元素移入队列
+    fn promote(&mut self, now: Instant) {
+        let (due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(at, _)| *at <= now);
+        self.delayed = pending;
+        for (_, item) in due {
+            self.push(item);
+        }
+    }
+}
+
+/// [`Controller`] 中 reconcile 函数的返回值
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// 等待对象的下一次变化
+    Done,
+    /// 在指定时间后再次 reconcile
+    RequeueAfter(Duration),
+}
+
+/// 基于 [`Informer`] 和 [`WorkQueue`] 的控制器.
+///
+/// 对象变化时将其键加入队列, 多个 worker 线程并发调用 reconcile 函数, 同一个对象不会被并发处理.
+/// reconcile 返回错误时按失败次数指数退避后重试. 已删除的对象不会被 reconcile.
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use k8s_openapi::api::apps::v1::Deployment;
+/// use rust_notes::k8s::api::HttpClient;
+/// use rust_notes::k8s::controller::{Action, Controller};
+/// use rust_notes::k8s::error::K8sError;
+/// use rust_notes::k8s::models::HttpKubeConfig;
+///
+/// let client = Arc::new(HttpClient::new(HttpKubeConfig::infer().unwrap()).unwrap());
+/// Controller::<Deployment>::new(client, None, &[])
+///     .workers(4)
+///     .run(|deployment, _store| -> Result<Action, K8sError> {
+///         println!("reconcile {:?}", deployment.metadata.name);
+///         Ok(Action::RequeueAfter(Duration::from_secs(300)))
+///     });
+/// ```
+pub struct Controller<K> {
+    informer: Informer<K>,
+    queue: Arc<WorkQueue<ObjectKey>>,
+    workers: usize,
+}
+
+impl<K> Controller<K>
+where
+    K: Resource + ListableResource + Metadata<Ty = ObjectMeta> + DeserializeOwned,
+    K: Send + Sync + 'static,
+    K::Scope: 'static,
+{
+    /// 命名空间资源在 `namespace` 为 `None` 时监听所有命名空间, `args` 可以包含标签选择器
+    pub fn new(client: Arc<HttpClient>, namespace: Option<&str>, args: &[&str]) -> Self {
+        Controller {
+            informer: Informer::new(client, namespace, args),
+            queue: Arc::new(WorkQueue::new(
+                Duration::from_millis(500),
+                Duration::from_secs(300),
+            )),
+            workers: 1,
+        }
+    }
+
+    /// worker 线程的数量, 默认为 1
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// reconcile 失败后的退避时间, 每次失败翻倍, 默认为 500ms 到 5 分钟
+    pub fn backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.queue = Arc::new(WorkQueue::new(initial_backoff, max_backoff));
+        self
+    }
+
+    pub fn store(&self) -> Store<K> {
+        self.informer.store()
+    }
+
+    /// 工作队列, 可以手动加入对象的键, 调用 [`WorkQueue::shutdown`] 停止控制器
+    pub fn queue(&self) -> Arc<WorkQueue<ObjectKey>> {
+        self.queue.clone()
+    }
+
+    /// 阻塞运行, 直到工作队列被关闭
+    pub fn run<F, E>(self, reconcile: F)
+    where
+        F: Fn(Arc<K>, &Store<K>) -> Result<Action, E> + Sync,
+        E: Display,
+    {
+        // informer 在阻塞读取 watch 响应, 使用独立的线程, 停止后在下一个事件时退出
+        let informer = self.informer.clone();
+        let queue = self.queue.clone();
+        std::thread::spawn(move || informer.run(|key| queue.add(key.clone())));
+
+        let store = self.informer.store();
+        std::thread::scope(|scope| {
+            for _ in 0..self.workers {
+                scope.spawn(|| {
+                    while let Some(key) = self.queue.get() {
+                        self.reconcile_key(&key, &store, &reconcile);
+                        self.queue.done(&key);
+                    }
+                });
+            }
+        });
+        self.informer.stop();
+    }
+
+    fn reconcile_key<F, E>(&self, key: &ObjectKey, store: &Store<K>, reconcile: &F)
+    where
+        F: Fn(Arc<K>, &Store<K>) -> Result<Action, E>,
+        E: Display,
+    {
+        let Some(object) = store.get(key) else {
+            self.queue.forget(key);
+            return;
+        };
+        match reconcile(object, store) {
+            Ok(action) => {
+                self.queue.forget(key);
+                if let Action::RequeueAfter(delay) = action {
+                    self.queue.add_after(key.clone(), delay);
+                }
+            }
+            Err(e) => {
+                log::warn!(
+                    "reconcile {} {} failed ({} times): {}",
+                    K::KIND,
+                    key,
+                    self.queue.failures(key) + 1,
+                    e
+                );
+                self.queue.add_rate_limited(key.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue() -> WorkQueue<&'static str> {
+        WorkQueue::new(Duration::from_millis(10), Duration::from_millis(40))
+    }
+
+    #[test]
+    fn dedup_test() {
+        let queue = queue();
+        queue.add("a");
+        queue.add("b");
+        queue.add("a");
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.get(), Some("a"));
+        // 处理中的元素再次加入时, 完成后才重新入队
+        queue.add("a");
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.get(), Some("b"));
+        queue.done(&"b");
+        assert!(queue.is_empty());
+        queue.done(&"a");
+        assert_eq!(queue.get(), Some("a"));
+    }
+
+    #[test]
+    fn delay_test() {
+        let queue = queue();
+        let start = Instant::now();
+        queue.add_after("a", Duration::from_millis(30));
+        assert!(queue.is_empty());
+        assert_eq!(queue.get(), Some("a"));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        queue.done(&"a");
+
+        queue.add_rate_limited("b");
+        queue.add_rate_limited("b");
+        assert_eq!(queue.failures(&"b"), 2);
+        queue.forget(&"b");
+        assert_eq!(queue.failures(&"b"), 0);
+    }
+
+    #[test]
+    fn shutdown_test() {
+        let queue = Arc::new(queue());
+        let worker = {
+            let queue = queue.clone();
+            std::thread::spawn(move || queue.get())
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        queue.shutdown();
+        assert_eq!(worker.join().unwrap(), None);
+        queue.add("a");
+        assert!(queue.is_empty());
+    }
+}
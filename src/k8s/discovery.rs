@@ -0,0 +1,250 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{APIGroupList, APIResourceList, APIVersions};
+use kube::core::{DynamicObject, ObjectList};
+use reqwest::Method;
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::error::K8sError;
+use crate::k8s::resource::{group_version_path, object_path};
+
+/// 通过 discovery 接口发现的资源
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveredResource {
+    /// API 组, core 组为空字符串
+    pub group: String,
+    pub version: String,
+    pub kind: String,
+    /// URL 中使用的复数名称, 例如 `deployments`
+    pub plural: String,
+    pub namespaced: bool,
+    /// 支持的操作, 例如 `get`, `list`, `watch`, `create`
+    pub verbs: Vec<String>,
+}
+
+impl DiscoveredResource {
+    /// `apiVersion` 字段的值, 例如 `v1`, `apps/v1`
+    pub fn api_version(&self) -> String {
+        if self.group.is_empty() {
+            self.version.clone()
+        } else {
+            format!("{}/{}", self.group, self.version)
+        }
+    }
+
+    pub fn supports(&self, verb: &str) -> bool {
+        self.verbs.iter().any(|v| v == verb)
+    }
+
+    /// 资源的 URL 路径, 规则与 [`resource_path`][crate::k8s::resource::resource_path] 相同
+    pub fn path(&self, namespace: Option<&str>, name: Option<&str>) -> String {
+        object_path(
+            &group_version_path(&self.group, &self.version),
+            &self.plural,
+            self.namespaced,
+            namespace,
+            name,
+        )
+    }
+}
+
+/// 遍历 `/api` 和 `/apis` 得到的 `apiVersion` + `kind` 到资源的映射, 包含 CRD
+#[derive(Clone, Debug, Default)]
+pub struct Discovery {
+    resources: HashMap<(String, String), DiscoveredResource>,
+}
+
+impl Discovery {
+    /// 遍历所有的 group/version, 不可用的聚合 API (例如未就绪的 metrics-server) 会被跳过
+    pub fn run(client: &HttpClient) -> Result<Self, K8sError> {
+        let mut discovery = Discovery::default();
+        let url = client.url(&["/api"], &[]);
+        let core: APIVersions = client.send(client.request(Method::GET, &url)?)?.json()?;
+        for version in core.versions {
+            discovery.discover(client, "", &version)?;
+        }
+        let url = client.url(&["/apis"], &[]);
+        let groups: APIGroupList = client.send(client.request(Method::GET, &url)?)?.json()?;
+        for group in groups.groups {
+            for version in group.versions {
+                discovery.discover(client, &group.name, &version.version)?;
+            }
+        }
+        Ok(discovery)
+    }
+
+    fn discover(
+        &mut self,
+        client: &HttpClient,
+        group: &str,
+        version: &str,
+    ) -> Result<(), K8sError> {
+        let url = client.url(&[&group_version_path(group, version)], &[]);
+        let list: APIResourceList = match client.send(client.request(Method::GET, &url)?) {
+            Ok(response) => response.json()?,
+            Err(K8sError::Api(error)) if error.code == 503 || error.code == 404 => {
+                log::warn!("discovery: skip {}/{}: {}", group, version, error);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        self.extend(group, version, list);
+        Ok(())
+    }
+
+    fn extend(&mut self, group: &str, version: &str, list: APIResourceList) {
+        // 忽略 pods/log, deployments/scale 等子资源
+        for resource in list.resources.into_iter().filter(|r| !r.name.contains('/')) {
+            let resource = DiscoveredResource {
+                group: group.to_string(),
+                version: version.to_string(),
+                kind: resource.kind,
+                plural: resource.name,
+                namespaced: resource.namespaced,
+                verbs: resource.verbs,
+            };
+            self.resources
+                .insert((resource.api_version(), resource.kind.clone()), resource);
+        }
+    }
+
+    /// 根据 `apiVersion` 和 `kind` 查找资源
+    pub fn resolve(&self, api_version: &str, kind: &str) -> Option<&DiscoveredResource> {
+        self.resources
+            .get(&(api_version.to_string(), kind.to_string()))
+    }
+
+    pub fn resources(&self) -> impl Iterator<Item = &DiscoveredResource> {
+        self.resources.values()
+    }
+}
+
+impl HttpClient {
+    /// 返回缓存的 discovery 结果, 首次调用时遍历 apiserver
+    pub fn discovery(&self) -> Result<Arc<Discovery>, K8sError> {
+        let mut cache = self.discovery.lock().unwrap();
+        if let Some(discovery) = cache.as_ref() {
+            return Ok(discovery.clone());
+        }
+        let discovery = Arc::new(Discovery::run(self)?);
+        *cache = Some(discovery.clone());
+        Ok(discovery)
+    }
+
+    /// 重新遍历 apiserver, 例如在安装了新的 CRD 之后
+    pub fn refresh_discovery(&self) -> Result<Arc<Discovery>, K8sError> {
+        self.discovery.lock().unwrap().take();
+        self.discovery()
+    }
+
+    /// 只重新查询一个 group/version, 例如在对应的 CRD 创建之后
+    pub fn refresh_group_version(&self, api_version: &str) -> Result<Arc<Discovery>, K8sError> {
+        let (group, version) = api_version.split_once('/').unwrap_or(("", api_version));
+        let mut discovery = self.discovery()?.as_ref().clone();
+        discovery
+            .resources
+            .retain(|(resource_api_version, _), _| resource_api_version != api_version);
+        discovery.discover(self, group, version)?;
+        let discovery = Arc::new(discovery);
+        *self.discovery.lock().unwrap() = Some(discovery.clone());
+        Ok(discovery)
+    }
+
+    /// 根据 `apiVersion` 和 `kind` 查找资源, 找不到时刷新一次该 group/version 的缓存
+    pub fn resolve(&self, api_version: &str, kind: &str) -> Result<DiscoveredResource, K8sError> {
+        if let Some(resource) = self.discovery()?.resolve(api_version, kind) {
+            return Ok(resource.clone());
+        }
+        self.refresh_group_version(api_version)?
+            .resolve(api_version, kind)
+            .cloned()
+            .ok_or_else(|| {
+                K8sError::Validation(format!(
+                    "no resource found for apiVersion '{}' kind '{}'",
+                    api_version, kind
+                ))
+            })
+    }
+
+    /// 以动态对象查询资源列表, 命名空间资源在 `namespace` 为 `None` 时查询所有命名空间
+    pub fn list_dynamic(
+        &self,
+        api_version: &str,
+        kind: &str,
+        namespace: Option<&str>,
+        args: &[&str],
+    ) -> Result<ObjectList<DynamicObject>, K8sError> {
+        let resource = self.resolve(api_version, kind)?;
+        let url = self.url(&[&resource.path(namespace, None)], args);
+        Ok(self.send(self.request(Method::GET, &url)?)?.json()?)
+    }
+
+    /// 以动态对象查询单个资源, `namespace` 为 `None` 时使用 context 的默认命名空间
+    pub fn get_dynamic(
+        &self,
+        api_version: &str,
+        kind: &str,
+        namespace: Option<&str>,
+        name: &str,
+    ) -> Result<DynamicObject, K8sError> {
+        let resource = self.resolve(api_version, kind)?;
+        let namespace = namespace.unwrap_or(self.default_namespace());
+        let url = self.url(&[&resource.path(Some(namespace), Some(name))], &[]);
+        Ok(self.send(self.request(Method::GET, &url)?)?.json()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extend_test() {
+        let list: APIResourceList = serde_json::from_str(
+            r#"{"kind":"APIResourceList","apiVersion":"v1","groupVersion":"stable.example.com/v1","resources":[
+                {"name":"crontabs","singularName":"crontab","namespaced":true,"kind":"CronTab","verbs":["get","list","watch","create"]},
+                {"name":"crontabs/status","singularName":"","namespaced":true,"kind":"CronTab","verbs":["get","patch"]}
+            ]}"#,
+        )
+        .unwrap();
+        let mut discovery = Discovery::default();
+        discovery.extend("stable.example.com", "v1", list);
+        assert_eq!(discovery.resources().count(), 1);
+
+        let resource = discovery
+            .resolve("stable.example.com/v1", "CronTab")
+            .unwrap();
+        assert_eq!(resource.plural, "crontabs");
+        assert!(resource.supports("watch"));
+        assert_eq!(
+            resource.path(Some("default"), Some("cron")),
+            "/apis/stable.example.com/v1/namespaces/default/crontabs/cron"
+        );
+        assert!(discovery.resolve("v1", "CronTab").is_none());
+    }
+}
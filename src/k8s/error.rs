@@ -0,0 +1,238 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::fmt;
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Status, StatusCause};
+use reqwest::StatusCode;
+
+/// k8s 模块中所有公开函数返回的错误类型
+#[derive(Debug)]
+pub enum K8sError {
+    /// kubeconfig 或 in-cluster 配置错误, 例如缺少 context, 无效的 base64 数据
+    Config(String),
+
+    /// 加载 CA 证书或客户端证书失败
+    Tls(String),
+
+    /// 获取认证信息失败, 例如读取 token 文件或执行 exec 插件失败
+    Auth(String),
+
+    /// 请求参数不合法, 例如标签选择器的键或值不符合规则
+    Validation(String),
+
+    /// 网络传输错误, 例如连接失败, 超时
+    Transport(reqwest::Error),
+
+    /// apiserver 返回的 `Status` 失败信息
+    Api(ApiError),
+
+    /// 解析响应内容失败
+    Decode(String),
+
+    /// exec 等 WebSocket 连接的错误
+    WebSocket(Box<tungstenite::Error>),
+}
+
+/// apiserver 返回的 `Status` 对象, 参考 [API Conventions](https://github.com/kubernetes/community/blob/master/contributors/devel/sig-architecture/api-conventions.md#response-status-kind)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ApiError {
+    /// HTTP 状态码, 例如 404
+    pub code: u16,
+
+    /// 机器可读的原因, 例如 `NotFound`, `AlreadyExists`, `Conflict`
+    pub reason: String,
+
+    /// 人类可读的错误描述
+    pub message: String,
+
+    /// `details.causes` 中的字段级错误
+    pub causes: Vec<StatusCause>,
+}
+
+impl ApiError {
+    /// 从响应状态码和响应体中解析 `Status`, 响应体不是 `Status` 时使用原始内容作为 `message`
+    pub fn from_response(code: StatusCode, body: &str) -> Self {
+        match serde_json::from_str::<Status>(body) {
+            Ok(status) if status.message.is_some() || status.reason.is_some() => {
+                let mut error = ApiError::from(status);
+                error.code = code.as_u16();
+                error
+            }
+            _ => ApiError {
+                code: code.as_u16(),
+                reason: code.canonical_reason().unwrap_or_default().to_string(),
+                message: body.trim().to_string(),
+                causes: vec![],
+            },
+        }
+    }
+}
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        ApiError {
+            code: status.code.unwrap_or_default() as u16,
+            reason: status.reason.unwrap_or_default(),
+            message: status.message.unwrap_or_default(),
+            causes: status
+                .details
+                .and_then(|details| details.causes)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {}", self.code, self.reason, self.message)?;
+        for cause in &self.causes {
+            write!(
+                f,
+                "; {}: {}",
+                cause.field.as_deref().unwrap_or_default(),
+                cause.message.as_deref().unwrap_or_default()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl K8sError {
+    /// apiserver 返回的 HTTP 状态码, 非 [`K8sError::Api`] 错误时返回 `None`
+    pub fn code(&self) -> Option<u16> {
+        match self {
+            K8sError::Api(error) => Some(error.code),
+            _ => None,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.code() == Some(404)
+    }
+
+    pub fn is_conflict(&self) -> bool {
+        self.code() == Some(409)
+    }
+
+    pub fn is_gone(&self) -> bool {
+        self.code() == Some(410)
+    }
+}
+
+impl fmt::Display for K8sError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            K8sError::Config(message) => write!(f, "config error: {}", message),
+            K8sError::Tls(message) => write!(f, "tls error: {}", message),
+            K8sError::Auth(message) => write!(f, "auth error: {}", message),
+            K8sError::Validation(message) => write!(f, "validation error: {}", message),
+            K8sError::Transport(error) => write!(f, "transport error: {}", error),
+            K8sError::Api(error) => write!(f, "api error: {}", error),
+            K8sError::Decode(message) => write!(f, "decode error: {}", message),
+            K8sError::WebSocket(error) => write!(f, "websocket error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for K8sError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            K8sError::Transport(error) => Some(error),
+            K8sError::WebSocket(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for K8sError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            K8sError::Decode(error.to_string())
+        } else {
+            K8sError::Transport(error)
+        }
+    }
+}
+
+impl From<tungstenite::Error> for K8sError {
+    /// 握手时 apiserver 返回的非 101 响应转换为 [`K8sError::Api`]
+    fn from(error: tungstenite::Error) -> Self {
+        match error {
+            tungstenite::Error::Http(response) => {
+                let body = response.body().as_deref().unwrap_or_default();
+                K8sError::Api(ApiError::from_response(
+                    response.status(),
+                    &String::from_utf8_lossy(body),
+                ))
+            }
+            error => K8sError::WebSocket(Box::new(error)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for K8sError {
+    fn from(error: serde_json::Error) -> Self {
+        K8sError::Decode(error.to_string())
+    }
+}
+
+impl From<ApiError> for K8sError {
+    fn from(error: ApiError) -> Self {
+        K8sError::Api(error)
+    }
+}
+
+impl From<kube::config::KubeconfigError> for K8sError {
+    fn from(error: kube::config::KubeconfigError) -> Self {
+        K8sError::Config(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_error_test() {
+        let body = r#"{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","message":"Pod \"nginx\" is invalid","reason":"Invalid","details":{"name":"nginx","kind":"Pod","causes":[{"reason":"FieldValueRequired","message":"Required value","field":"spec.containers"}]},"code":422}"#;
+        let error = K8sError::from(ApiError::from_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            body,
+        ));
+        assert_eq!(error.code(), Some(422));
+        assert_eq!(
+            error.to_string(),
+            "api error: 422 Invalid: Pod \"nginx\" is invalid; spec.containers: Required value"
+        );
+    }
+
+    #[test]
+    fn plain_body_test() {
+        let error = ApiError::from_response(StatusCode::NOT_FOUND, "404 page not found\n");
+        assert_eq!(error.reason, "Not Found");
+        assert_eq!(error.message, "404 page not found");
+        assert!(K8sError::Api(error).is_not_found());
+    }
+}
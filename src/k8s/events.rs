@@ -0,0 +1,594 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::events::v1::Event as K8sEvent;
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::error::K8sError;
+use crate::k8s::params::{FieldSelector, ListParams};
+use crate::k8s::watch::Event;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+
+/// 合并事件的键: 命名空间, 类型, 原因, 对象类型, 对象名称和内容
+type EventKey = (String, String, String, String, String, String);
+
+/// 输出的一条事件. 相同命名空间, 类型, 原因, 对象和内容的事件合并为一行, `count` 为累计发生的次数
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventLine {
+    pub time: Option<DateTime<Utc>>,
+    pub namespace: String,
+    /// `Normal` 或 `Warning`
+    pub event_type: String,
+    pub reason: String,
+    pub kind: String,
+    pub name: String,
+    pub note: String,
+    pub count: i32,
+}
+
+impl EventLine {
+    fn of(event: &K8sEvent) -> Self {
+        let regarding = event.regarding.clone().unwrap_or_default();
+        let time = event
+            .series
+            .as_ref()
+            .map(|series| series.last_observed_time.0)
+            .or_else(|| event.event_time.as_ref().map(|time| time.0))
+            .or_else(|| event.deprecated_last_timestamp.as_ref().map(|time| time.0))
+            .or_else(|| {
+                event
+                    .metadata
+                    .creation_timestamp
+                    .as_ref()
+                    .map(|time| time.0)
+            });
+        EventLine {
+            time,
+            namespace: event.metadata.namespace.clone().unwrap_or_default(),
+            event_type: event.type_.clone().unwrap_or_default(),
+            reason: event.reason.clone().unwrap_or_default(),
+            kind: regarding.kind.unwrap_or_default(),
+            name: regarding.name.unwrap_or_default(),
+            note: event.note.clone().unwrap_or_default(),
+            count: 0,
+        }
+    }
+
+    /// 使用 ANSI 颜色: `Warning` 为黄色, 原因包含 `Fail`, `Error` 或 `BackOff` 时为红色, `Normal` 为绿色
+    pub fn colored(&self) -> String {
+        let color = match self.event_type.as_str() {
+            "Normal" => GREEN,
+            _ if ["Fail", "Error", "BackOff"]
+                .iter()
+                .any(|word| self.reason.contains(word)) =>
+            {
+                RED
+            }
+            _ => YELLOW,
+        };
+        let mut line = format!(
+            "{}{}{} {}{}{} {}{}{}{} {}{}{} {}: {}",
+            DIM,
+            self.time_string(),
+            RESET,
+            CYAN,
+            self.namespace,
+            RESET,
+            color,
+            BOLD,
+            self.event_type,
+            RESET,
+            color,
+            self.reason,
+            RESET,
+            self.object(),
+            self.note
+        );
+        if self.count > 1 {
+            line.push_str(&format!(" {}(x{}){}", BOLD, self.count, RESET));
+        }
+        line
+    }
+
+    fn time_string(&self) -> String {
+        self.time
+            .map(|time| time.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    /// 与 kubectl 相同的 `kind/name` 形式, 例如 `pod/nginx-0`
+    fn object(&self) -> String {
+        format!("{}/{}", self.kind.to_lowercase(), self.name)
+    }
+
+    fn key(&self) -> EventKey {
+        (
+            self.namespace.clone(),
+            self.event_type.clone(),
+            self.reason.clone(),
+            self.kind.clone(),
+            self.name.clone(),
+            self.note.clone(),
+        )
+    }
+}
+
+impl fmt::Display for EventLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}: {}",
+            self.time_string(),
+            self.namespace,
+            self.event_type,
+            self.reason,
+            self.object(),
+            self.note
+        )?;
+        if self.count > 1 {
+            write!(f, " (x{})", self.count)?;
+        }
+        Ok(())
+    }
+}
+
+/// [`EventDeduplicator`] 默认最多记录的行数
+const DEDUPLICATOR_CAPACITY: usize = 10_000;
+
+/// 一行事件对应的对象和最后一次更新的序号
+#[derive(Debug, Default)]
+struct SeenLine {
+    /// 对象的 uid 和发生次数
+    objects: HashMap<String, i32>,
+    tick: u64,
+}
+
+/// 合并重复的事件.
+///
+/// apiserver 会将重复的事件合并到同一个对象的 `series.count` 中, 也可能为同样的内容创建新的对象.
+/// 按对象记录发生次数, 同一行的次数为所有对象之和, 重新 list 或对象没有变化时不会重复输出.
+///
+/// 事件对象被删除 (默认 1 小时后过期) 时调用 [`EventDeduplicator::forget`] 释放记录,
+/// 记录的行数超过容量时丢弃最久没有更新的行.
+#[derive(Debug)]
+pub struct EventDeduplicator {
+    seen: HashMap<EventKey, SeenLine>,
+    capacity: usize,
+    tick: u64,
+}
+
+impl Default for EventDeduplicator {
+    fn default() -> Self {
+        EventDeduplicator::with_capacity(DEDUPLICATOR_CAPACITY)
+    }
+}
+
+impl EventDeduplicator {
+    /// 最多记录 `capacity` 行, 至少为 1
+    pub fn with_capacity(capacity: usize) -> Self {
+        EventDeduplicator {
+            seen: HashMap::new(),
+            capacity: capacity.max(1),
+            tick: 0,
+        }
+    }
+
+    /// 累计次数增加时返回需要输出的行
+    pub fn observe(&mut self, event: &K8sEvent) -> Option<EventLine> {
+        let mut line = EventLine::of(event);
+        let occurrences = event
+            .series
+            .as_ref()
+            .map(|series| series.count)
+            .or(event.deprecated_count)
+            .unwrap_or(1)
+            .max(1);
+        self.tick += 1;
+        let seen = self.seen.entry(line.key()).or_default();
+        seen.tick = self.tick;
+        let previous = seen.objects.insert(object_id(event), occurrences);
+        if previous.is_some_and(|previous| previous >= occurrences) {
+            return None;
+        }
+        line.count = seen.objects.values().sum();
+        if self.seen.len() > self.capacity {
+            self.evict();
+        }
+        Some(line)
+    }
+
+    /// 事件对象被删除后不再记录, 同一行的对象都被删除时释放这一行
+    pub fn forget(&mut self, event: &K8sEvent) {
+        let key = EventLine::of(event).key();
+        if let Some(seen) = self.seen.get_mut(&key) {
+            seen.objects.remove(&object_id(event));
+            if seen.objects.is_empty() {
+                self.seen.remove(&key);
+            }
+        }
+    }
+
+    /// 记录的行数
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// 保留最近更新的 3/4, 避免每个新行都要清理一次
+    fn evict(&mut self) {
+        let keep = (self.capacity * 3 / 4).max(1);
+        let mut ticks: Vec<u64> = self.seen.values().map(|seen| seen.tick).collect();
+        ticks.sort_unstable();
+        let oldest = ticks[ticks.len().saturating_sub(keep)];
+        self.seen.retain(|_, seen| seen.tick >= oldest);
+    }
+}
+
+fn object_id(event: &K8sEvent) -> String {
+    event
+        .metadata
+        .uid
+        .clone()
+        .or_else(|| event.metadata.name.clone())
+        .unwrap_or_default()
+}
+
+/// 实时输出 `events.k8s.io/v1` 事件, 类似 `kubectl get events -w`, 支持多个命名空间和按类型, 对象类型, 原因过滤.
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use rust_notes::k8s::api::HttpClient;
+/// use rust_notes::k8s::events::EventTailer;
+/// use rust_notes::k8s::models::HttpKubeConfig;
+///
+/// let client = Arc::new(HttpClient::new(HttpKubeConfig::infer().unwrap()).unwrap());
+/// EventTailer::new(client)
+///     .namespace("default")
+///     .event_type("Warning")
+///     .kind("Pod")
+///     .run(|line| {
+///         println!("{}", line.colored());
+///         true
+///     })
+///     .unwrap();
+/// ```
+pub struct EventTailer {
+    client: Arc<HttpClient>,
+    namespaces: Vec<String>,
+    event_types: Vec<String>,
+    kinds: Vec<String>,
+    reasons: Vec<String>,
+}
+
+impl EventTailer {
+    /// 默认监听所有命名空间的所有事件
+    pub fn new(client: Arc<HttpClient>) -> Self {
+        EventTailer {
+            client,
+            namespaces: vec![],
+            event_types: vec![],
+            kinds: vec![],
+            reasons: vec![],
+        }
+    }
+
+    /// 只监听指定的命名空间, 可以多次调用. 只需要这些命名空间中 events 的 list 和 watch 权限
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespaces.push(namespace.to_string());
+        self
+    }
+
+    /// 事件类型: `Normal` 或 `Warning`, 可以多次调用
+    pub fn event_type(mut self, event_type: &str) -> Self {
+        self.event_types.push(event_type.to_string());
+        self
+    }
+
+    /// 事件关联对象的类型, 例如 `Pod`, 可以多次调用, 区分大小写. 只有一个时由 apiserver 过滤
+    pub fn kind(mut self, kind: &str) -> Self {
+        self.kinds.push(kind.to_string());
+        self
+    }
+
+    /// 事件的原因, 例如 `BackOff`, `FailedScheduling`, 可以多次调用, 区分大小写. 只有一个时由 apiserver 过滤
+    pub fn reason(mut self, reason: &str) -> Self {
+        self.reasons.push(reason.to_string());
+        self
+    }
+
+    /// 只有一个值的条件作为 `fieldSelector` 由 apiserver 过滤, 类型按 `Normal` 和 `Warning` 的大小写发送,
+    /// 对象类型和原因区分大小写
+    pub fn field_selector(&self) -> FieldSelector {
+        let mut selector = FieldSelector::new();
+        if let [event_type] = self.event_types.as_slice() {
+            let event_type = ["Normal", "Warning"]
+                .into_iter()
+                .find(|value| value.eq_ignore_ascii_case(event_type))
+                .unwrap_or(event_type);
+            selector = selector.eq("type", event_type);
+        }
+        if let [kind] = self.kinds.as_slice() {
+            selector = selector.eq("regarding.kind", kind);
+        }
+        if let [reason] = self.reasons.as_slice() {
+            selector = selector.eq("reason", reason);
+        }
+        selector
+    }
+
+    /// 是否匹配过滤条件, 未设置的条件匹配所有事件. 与 [`EventTailer::field_selector`] 一致,
+    /// 只有事件类型不区分大小写
+    pub fn matches(&self, event: &K8sEvent) -> bool {
+        let any = |values: &[String], value: Option<&str>, ignore_case: bool| {
+            values.is_empty()
+                || value.is_some_and(|value| {
+                    values.iter().any(|v| {
+                        if ignore_case {
+                            v.eq_ignore_ascii_case(value)
+                        } else {
+                            v == value
+                        }
+                    })
+                })
+        };
+        any(&self.event_types, event.type_.as_deref(), true)
+            && any(
+                &self.kinds,
+                event.regarding.as_ref().and_then(|r| r.kind.as_deref()),
+                false,
+            )
+            && any(&self.reasons, event.reason.as_deref(), false)
+    }
+
+    /// 阻塞运行, 先输出已有的事件, 再输出新的事件, 直到 `on_event` 返回 `false`.
+    ///
+    /// 网络错误和 apiserver 的 5xx, 429 错误会自动重试, 其他错误 (例如没有权限) 结束运行并返回错误.
+    pub fn run<F: FnMut(&EventLine) -> bool>(&self, mut on_event: F) -> Result<(), K8sError> {
+        let stopped = AtomicBool::new(false);
+        let (sender, receiver) = mpsc::channel();
+        let namespaces = if self.namespaces.is_empty() {
+            vec![None]
+        } else {
+            self.namespaces.iter().cloned().map(Some).collect()
+        };
+        let args = ListParams::new().fields(self.field_selector()).query()?;
+        // watch 会阻塞读取, 每个命名空间使用一个线程, 停止后等待所有线程结束再返回
+        std::thread::scope(|scope| {
+            for namespace in &namespaces {
+                let client = self.client.as_ref();
+                let (args, stopped, sender) = (&args, &stopped, sender.clone());
+                scope.spawn(move || {
+                    watch_events(client, namespace.as_deref(), args, stopped, &sender)
+                });
+            }
+            drop(sender);
+
+            let mut deduplicator = EventDeduplicator::default();
+            let result = loop {
+                let events = match receiver.recv() {
+                    Ok(Ok(Event::Restarted(events))) => events,
+                    Ok(Ok(Event::Added(event) | Event::Modified(event))) => vec![event],
+                    Ok(Ok(Event::Deleted(event))) => {
+                        deduplicator.forget(&event);
+                        continue;
+                    }
+                    Ok(Ok(Event::Bookmark(_))) => continue,
+                    Ok(Err(e)) => break Err(e),
+                    Err(_) => break Ok(()),
+                };
+                let mut lines = events
+                    .iter()
+                    .filter(|event| self.matches(event))
+                    .filter_map(|event| deduplicator.observe(event));
+                if !lines.all(|line| on_event(&line)) {
+                    break Ok(());
+                }
+            };
+            // watch 在等待事件时也会定期检查, 几秒内关闭连接并结束线程
+            stopped.store(true, Ordering::SeqCst);
+            result
+        })
+    }
+}
+
+/// 将一个命名空间的事件发送到 `sender`, list 的事件按时间排序
+fn watch_events(
+    client: &HttpClient,
+    namespace: Option<&str>,
+    args: &str,
+    stopped: &AtomicBool,
+    sender: &mpsc::Sender<Result<Event<K8sEvent>, K8sError>>,
+) {
+    for event in client
+        .watch::<K8sEvent>(namespace, &[args])
+        .stop_on(stopped)
+    {
+        let event = match event {
+            Ok(Event::Restarted(mut events)) => {
+                events.sort_by_key(|event| EventLine::of(event).time);
+                Event::Restarted(events)
+            }
+            Ok(Event::Bookmark(_)) => continue,
+            Ok(event) => event,
+            Err(e) if !matches!(e.code(), Some(code) if code < 500 && code != 429) => {
+                log::warn!(
+                    "watch events in {}: {}",
+                    namespace.unwrap_or("all namespaces"),
+                    e
+                );
+                continue;
+            }
+            Err(e) => {
+                let _ = sender.send(Err(e));
+                return;
+            }
+        };
+        if sender.send(Ok(event)).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::ObjectReference;
+    use k8s_openapi::api::events::v1::EventSeries;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::{MicroTime, ObjectMeta};
+
+    use super::*;
+
+    fn event(name: &str, reason: &str, count: Option<i32>) -> K8sEvent {
+        let time = DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        K8sEvent {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some("default".to_string()),
+                uid: Some(format!("uid-{}", name)),
+                ..Default::default()
+            },
+            type_: Some("Warning".to_string()),
+            reason: Some(reason.to_string()),
+            note: Some("Back-off restarting failed container".to_string()),
+            regarding: Some(ObjectReference {
+                kind: Some("Pod".to_string()),
+                name: Some("nginx-0".to_string()),
+                ..Default::default()
+            }),
+            event_time: Some(MicroTime(time)),
+            series: count.map(|count| EventSeries {
+                count,
+                last_observed_time: MicroTime(time),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn deduplicate_test() {
+        let mut deduplicator = EventDeduplicator::default();
+        let line = deduplicator.observe(&event("a", "BackOff", None)).unwrap();
+        assert_eq!(
+            line.to_string(),
+            "2024-01-02T03:04:05Z default Warning BackOff pod/nginx-0: Back-off restarting failed container"
+        );
+        // 同一个对象没有变化时不输出, series.count 增加时输出累计次数
+        assert_eq!(deduplicator.observe(&event("a", "BackOff", None)), None);
+        assert_eq!(
+            deduplicator
+                .observe(&event("a", "BackOff", Some(3)))
+                .unwrap()
+                .count,
+            3
+        );
+        // 内容相同的新对象合并到同一行
+        let line = deduplicator.observe(&event("b", "BackOff", None)).unwrap();
+        assert_eq!(line.count, 4);
+        assert!(line.to_string().ends_with("(x4)"));
+        assert!(line.colored().contains("\x1b[31mBackOff"));
+        assert_eq!(
+            deduplicator
+                .observe(&event("c", "Unhealthy", None))
+                .unwrap()
+                .count,
+            1
+        );
+    }
+
+    #[test]
+    fn forget_test() {
+        let mut deduplicator = EventDeduplicator::default();
+        deduplicator
+            .observe(&event("a", "BackOff", Some(2)))
+            .unwrap();
+        deduplicator.observe(&event("b", "BackOff", None)).unwrap();
+        assert_eq!(deduplicator.len(), 1);
+        deduplicator.forget(&event("a", "BackOff", None));
+        assert_eq!(deduplicator.len(), 1);
+        deduplicator.forget(&event("b", "BackOff", None));
+        assert!(deduplicator.is_empty());
+        // 删除后重新出现的内容从头计数
+        let line = deduplicator.observe(&event("c", "BackOff", None)).unwrap();
+        assert_eq!(line.count, 1);
+    }
+
+    #[test]
+    fn capacity_test() {
+        let mut deduplicator = EventDeduplicator::with_capacity(4);
+        for reason in ["A", "B", "C", "D"] {
+            deduplicator.observe(&event(reason, reason, None)).unwrap();
+        }
+        // 更新 A 后超过容量, 保留最近更新的 3 行
+        deduplicator.observe(&event("A", "A", Some(2))).unwrap();
+        deduplicator.observe(&event("E", "E", None)).unwrap();
+        assert_eq!(deduplicator.len(), 3);
+        assert!(deduplicator.observe(&event("A", "A", Some(2))).is_none());
+        assert!(deduplicator.observe(&event("E", "E", None)).is_none());
+        // 被丢弃的行会再次输出
+        assert!(deduplicator.observe(&event("B", "B", None)).is_some());
+    }
+
+    #[test]
+    fn field_selector_test() {
+        let client = Arc::new(HttpClient::new(Default::default()).unwrap());
+        let tailer = EventTailer::new(client.clone())
+            .event_type("warning")
+            .kind("Pod")
+            .reason("BackOff")
+            .reason("Unhealthy");
+        assert_eq!(
+            tailer.field_selector().to_string(),
+            "type=Warning,regarding.kind=Pod"
+        );
+        // 与 apiserver 一致, 只有事件类型不区分大小写
+        let event = event("A", "BackOff", None);
+        assert!(tailer.matches(&event));
+        let tailer = EventTailer::new(client.clone())
+            .kind("pod")
+            .reason("backoff")
+            .reason("Unhealthy");
+        assert!(!tailer.matches(&event));
+
+        let tailer = EventTailer::new(client).reason("BackOff");
+        assert_eq!(tailer.field_selector().to_string(), "reason=BackOff");
+    }
+}
@@ -0,0 +1,569 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::Duration;
+
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Status;
+use reqwest::{Method, Url};
+use rustls::{ClientConfig, ClientConnection, ServerName, StreamOwned};
+use serde_json::json;
+use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::HandshakeError;
+use tungstenite::http::HeaderValue;
+use tungstenite::{Message, WebSocket};
+
+use crate::k8s::api::{HttpClient, CONNECT_TIMEOUT};
+use crate::k8s::error::{ApiError, K8sError};
+use crate::k8s::models::HttpKubeConfig;
+use crate::k8s::params::encode;
+use crate::k8s::resource::resource_path;
+use crate::k8s::tls::{client_config, server_name};
+
+/// exec 使用的 WebSocket 子协议, 每条二进制消息的第一个字节为通道号
+pub const EXEC_PROTOCOL: &str = "v4.channel.k8s.io";
+
+const STDIN_CHANNEL: u8 = 0;
+const STDOUT_CHANNEL: u8 = 1;
+const STDERR_CHANNEL: u8 = 2;
+const ERROR_CHANNEL: u8 = 3;
+const RESIZE_CHANNEL: u8 = 4;
+
+/// 交互模式下读取输出的超时时间, 超时后检查是否有新的输入
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// exec 参数, 对应 `/api/v1/namespaces/{namespace}/pods/{name}/exec` 的查询参数
+///
+/// ```
+/// use rust_notes::k8s::exec::ExecParams;
+///
+/// let params = ExecParams::new().container("nginx").stdin().tty();
+/// assert_eq!(
+///     params.query(&["sh", "-c", "echo $HOME"]),
+///     "command=sh&command=-c&command=echo%20%24HOME&container=nginx&stdin=true&stdout=true&tty=true"
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecParams {
+    pub container: Option<String>,
+    pub stdin: bool,
+    pub stdout: bool,
+    pub stderr: bool,
+    pub tty: bool,
+}
+
+impl Default for ExecParams {
+    fn default() -> Self {
+        ExecParams {
+            container: None,
+            stdin: false,
+            stdout: true,
+            stderr: true,
+            tty: false,
+        }
+    }
+}
+
+impl ExecParams {
+    pub fn new() -> Self {
+        ExecParams::default()
+    }
+
+    /// 容器名称, Pod 中只有一个容器时可以省略
+    pub fn container(mut self, container: &str) -> Self {
+        self.container = Some(container.to_string());
+        self
+    }
+
+    /// 打开标准输入, 类似 `kubectl exec -i`
+    pub fn stdin(mut self) -> Self {
+        self.stdin = true;
+        self
+    }
+
+    /// 分配终端, 类似 `kubectl exec -t`. 终端模式下标准错误合并到标准输出
+    pub fn tty(mut self) -> Self {
+        self.tty = true;
+        self.stderr = false;
+        self
+    }
+
+    /// 生成编码后的查询字符串
+    pub fn query(&self, command: &[&str]) -> String {
+        let mut pairs: Vec<(&str, String)> = command
+            .iter()
+            .map(|arg| ("command", arg.to_string()))
+            .collect();
+        if let Some(container) = &self.container {
+            pairs.push(("container", container.to_string()));
+        }
+        for (key, enabled) in [
+            ("stdin", self.stdin),
+            ("stdout", self.stdout),
+            ("stderr", self.stderr),
+            ("tty", self.tty),
+        ] {
+            if enabled {
+                pairs.push((key, "true".to_string()));
+            }
+        }
+        pairs
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, encode(value)))
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+}
+
+/// 一次性执行的结果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExecOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_code: i32,
+}
+
+impl ExecOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+
+    pub fn stdout_string(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).into_owned()
+    }
+
+    pub fn stderr_string(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).into_owned()
+    }
+}
+
+/// 从容器读取的输出
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecEvent {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+    /// 命令结束, 之后连接会被关闭
+    Exit(i32),
+}
+
+/// 交互模式下发送给容器的输入
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecInput {
+    Stdin(Vec<u8>),
+    /// 终端大小, 仅在 [`ExecParams::tty`] 时有效
+    Resize {
+        width: u16,
+        height: u16,
+    },
+}
+
+/// exec 的 WebSocket 会话.
+///
+/// `v4.channel.k8s.io` 协议不能单独关闭标准输入, 等待标准输入结束的命令 (例如 `cat`) 需要由输入内容自行结束.
+pub struct ExecSession {
+    socket: WebSocket<ExecStream>,
+    exit_code: Option<i32>,
+}
+
+impl ExecSession {
+    pub fn write_stdin(&mut self, data: &[u8]) -> Result<(), K8sError> {
+        self.send(STDIN_CHANNEL, data)
+    }
+
+    /// 调整终端大小
+    pub fn resize(&mut self, width: u16, height: u16) -> Result<(), K8sError> {
+        let size = json!({"Width": width, "Height": height}).to_string();
+        self.send(RESIZE_CHANNEL, size.as_bytes())
+    }
+
+    /// 阻塞读取下一个输出, 连接关闭后返回 `None`. 命令以非 0 以外的原因失败时返回 [`K8sError::Api`]
+    pub fn read(&mut self) -> Result<Option<ExecEvent>, K8sError> {
+        loop {
+            match self.socket.read() {
+                Ok(message) => {
+                    if let Some(event) = self.decode(message)? {
+                        return Ok(Some(event));
+                    }
+                }
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// 一次性执行: 收集所有输出直到命令结束. 连接关闭前没有收到命令的退出状态时返回错误
+    pub fn output(mut self) -> Result<ExecOutput, K8sError> {
+        let mut output = ExecOutput::default();
+        while let Some(event) = self.read()? {
+            match event {
+                ExecEvent::Stdout(data) => output.stdout.extend(data),
+                ExecEvent::Stderr(data) => output.stderr.extend(data),
+                ExecEvent::Exit(_) => {}
+            }
+        }
+        output.exit_code = self.finish()?;
+        Ok(output)
+    }
+
+    /// 交互模式: 将 `input` 中的输入发送给容器, 输出写入 `stdout` 和 `stderr`, 返回命令的退出码,
+    /// 没有收到退出状态时返回错误.
+    ///
+    /// `input` 的发送端全部关闭后不再发送输入, 但会继续等待命令结束.
+    pub fn interact<O: Write, E: Write>(
+        mut self,
+        input: &Receiver<ExecInput>,
+        stdout: &mut O,
+        stderr: &mut E,
+    ) -> Result<i32, K8sError> {
+        self.socket
+            .get_ref()
+            .tcp()
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(tungstenite::Error::Io)?;
+        let mut input_closed = false;
+        loop {
+            while !input_closed {
+                match input.try_recv() {
+                    Ok(ExecInput::Stdin(data)) => self.write_stdin(&data)?,
+                    Ok(ExecInput::Resize { width, height }) => self.resize(width, height)?,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => input_closed = true,
+                }
+            }
+            let message = match self.socket.read() {
+                Ok(message) => message,
+                Err(tungstenite::Error::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    break
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let write = match self.decode(message)? {
+                Some(ExecEvent::Stdout(data)) => stdout.write_all(&data).and(stdout.flush()),
+                Some(ExecEvent::Stderr(data)) => stderr.write_all(&data).and(stderr.flush()),
+                _ => Ok(()),
+            };
+            write.map_err(tungstenite::Error::Io)?;
+        }
+        self.finish()
+    }
+
+    /// 连接关闭后的退出码, 例如 apiserver 或 kubelet 中断连接时没有退出状态
+    fn finish(&self) -> Result<i32, K8sError> {
+        self.exit_code.ok_or_else(|| {
+            K8sError::Decode("exec connection closed without an exit status".to_string())
+        })
+    }
+
+    fn send(&mut self, channel: u8, data: &[u8]) -> Result<(), K8sError> {
+        let mut frame = Vec::with_capacity(data.len() + 1);
+        frame.push(channel);
+        frame.extend_from_slice(data);
+        Ok(self.socket.send(Message::Binary(frame))?)
+    }
+
+    /// 解析一条消息, 忽略控制消息和只有通道号的空消息
+    fn decode(&mut self, message: Message) -> Result<Option<ExecEvent>, K8sError> {
+        let Message::Binary(mut data) = message else {
+            return Ok(None);
+        };
+        if data.len() <= 1 {
+            return Ok(None);
+        }
+        let channel = data.remove(0);
+        match channel {
+            STDOUT_CHANNEL => Ok(Some(ExecEvent::Stdout(data))),
+            STDERR_CHANNEL => Ok(Some(ExecEvent::Stderr(data))),
+            ERROR_CHANNEL => {
+                let code = exit_code(serde_json::from_slice(&data)?)?;
+                self.exit_code = Some(code);
+                Ok(Some(ExecEvent::Exit(code)))
+            }
+            channel => Err(K8sError::Decode(format!(
+                "unknown exec channel {}",
+                channel
+            ))),
+        }
+    }
+}
+
+/// 错误通道中的 `Status`: 成功为 0, `NonZeroExitCode` 为命令的退出码, 其他失败 (例如找不到命令) 返回错误
+fn exit_code(status: Status) -> Result<i32, K8sError> {
+    if status.status.as_deref() == Some("Success") {
+        return Ok(0);
+    }
+    if status.reason.as_deref() == Some("NonZeroExitCode") {
+        let code = status
+            .details
+            .as_ref()
+            .and_then(|details| details.causes.as_ref())
+            .and_then(|causes| {
+                causes
+                    .iter()
+                    .find(|cause| cause.reason.as_deref() == Some("ExitCode"))
+            })
+            .and_then(|cause| cause.message.as_deref())
+            .and_then(|message| message.parse().ok());
+        if let Some(code) = code {
+            return Ok(code);
+        }
+    }
+    Err(K8sError::Api(ApiError::from(status)))
+}
+
+impl HttpClient {
+    /// 在容器中执行命令, 类似 `kubectl exec`. `namespace` 为 `None` 时使用 context 的默认命名空间.
+    ///
+    /// ```no_run
+    /// use rust_notes::k8s::api::HttpClient;
+    /// use rust_notes::k8s::exec::ExecParams;
+    /// use rust_notes::k8s::models::HttpKubeConfig;
+    ///
+    /// let client = HttpClient::new(HttpKubeConfig::infer().unwrap()).unwrap();
+    /// let output = client
+    ///     .exec(None, "nginx-0", &["nginx", "-v"], &ExecParams::new())
+    ///     .unwrap()
+    ///     .output()
+    ///     .unwrap();
+    /// println!("{} {}", output.exit_code, output.stderr_string());
+    /// ```
+    pub fn exec(
+        &self,
+        namespace: Option<&str>,
+        name: &str,
+        command: &[&str],
+        params: &ExecParams,
+    ) -> Result<ExecSession, K8sError> {
+        if command.is_empty() {
+            return Err(K8sError::Validation("exec requires a command".to_string()));
+        }
+        let namespace = namespace.unwrap_or(self.default_namespace());
+        let path = format!("{}/exec", resource_path::<Pod>(Some(namespace), Some(name)));
+        let url = self.url(&[&path], &[&params.query(command)]);
+        // 复用 HTTP 请求的认证和模拟用户请求头
+        let headers = self.request(Method::GET, &url)?.build()?.headers().clone();
+
+        let mut url = Url::parse(&url).map_err(|e| K8sError::Config(e.to_string()))?;
+        let secure = url.scheme() == "https";
+        // 只修改 http(s) 到 ws(s), 不会失败
+        let _ = url.set_scheme(if secure { "wss" } else { "ws" });
+        let mut request = url.as_str().into_client_request()?;
+        request.headers_mut().extend(headers);
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(EXEC_PROTOCOL),
+        );
+
+        // WebSocket 连接不经过 reqwest, 单独获取限流令牌
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire();
+        }
+        let stream = self.websocket()?.connect(&url, secure)?;
+        let (socket, _) = tungstenite::client(request, stream).map_err(|e| match e {
+            HandshakeError::Failure(e) => K8sError::from(e),
+            HandshakeError::Interrupted(_) => {
+                K8sError::from(tungstenite::Error::Io(io::ErrorKind::WouldBlock.into()))
+            }
+        })?;
+        Ok(ExecSession {
+            socket,
+            exit_code: None,
+        })
+    }
+
+    /// 不使用 exec 的客户端不需要解析证书
+    fn websocket(&self) -> Result<&WebSocketConnector, K8sError> {
+        if let Some(websocket) = self.websocket.get() {
+            return Ok(websocket);
+        }
+        let websocket = WebSocketConnector::new(&self.tls_config)?;
+        Ok(self.websocket.get_or_init(|| websocket))
+    }
+}
+
+/// WebSocket 的连接参数, reqwest 不支持协议升级, 需要单独建立 TCP 和 TLS 连接
+pub(crate) struct WebSocketConnector {
+    tls: Arc<ClientConfig>,
+    /// `tls-server-name`, 同时作为 SNI
+    server_name: Option<ServerName>,
+    proxy: bool,
+}
+
+impl WebSocketConnector {
+    pub(crate) fn new(http_config: &HttpKubeConfig) -> Result<Self, K8sError> {
+        Ok(WebSocketConnector {
+            tls: Arc::new(client_config(http_config)?),
+            server_name: server_name(http_config)?,
+            proxy: !http_config.proxy_url.is_empty(),
+        })
+    }
+
+    fn connect(&self, url: &Url, secure: bool) -> Result<ExecStream, K8sError> {
+        if self.proxy {
+            return Err(K8sError::Config(
+                "exec does not support proxy-url".to_string(),
+            ));
+        }
+        let host = url
+            .host_str()
+            .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+            .ok_or_else(|| K8sError::Config(format!("server {} has no host", url)))?;
+        let port = url.port_or_known_default().unwrap_or(443);
+        let tcp = connect_timeout(host, port).map_err(tungstenite::Error::Io)?;
+        tcp.set_nodelay(true).map_err(tungstenite::Error::Io)?;
+        if !secure {
+            return Ok(ExecStream::Plain(tcp));
+        }
+        let server_name = match &self.server_name {
+            Some(server_name) => server_name.clone(),
+            None => ServerName::try_from(host)
+                .map_err(|e| K8sError::Tls(format!("invalid server name {}: {}", host, e)))?,
+        };
+        let connection = ClientConnection::new(self.tls.clone(), server_name)
+            .map_err(|e| K8sError::Tls(e.to_string()))?;
+        Ok(ExecStream::Tls(Box::new(StreamOwned::new(connection, tcp))))
+    }
+}
+
+/// 依次尝试解析出的每个地址, 每个地址最多等待 [`CONNECT_TIMEOUT`]
+fn connect_timeout(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(tcp) => return Ok(tcp),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} resolved to no addresses", host),
+        )
+    }))
+}
+
+enum ExecStream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl ExecStream {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            ExecStream::Plain(stream) => stream,
+            ExecStream::Tls(stream) => &stream.sock,
+        }
+    }
+}
+
+impl Read for ExecStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ExecStream::Plain(stream) => stream.read(buf),
+            ExecStream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for ExecStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ExecStream::Plain(stream) => stream.write(buf),
+            ExecStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ExecStream::Plain(stream) => stream.flush(),
+            ExecStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(json: serde_json::Value) -> Status {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn exit_code_test() {
+        assert_eq!(exit_code(status(json!({"status": "Success"}))).unwrap(), 0);
+        let failure = json!({
+            "status": "Failure",
+            "reason": "NonZeroExitCode",
+            "message": "command terminated with non-zero exit code",
+            "details": {"causes": [{"reason": "ExitCode", "message": "3"}]}
+        });
+        assert_eq!(exit_code(status(failure)).unwrap(), 3);
+        let error = exit_code(status(json!({
+            "status": "Failure",
+            "message": "executable file not found in $PATH"
+        })))
+        .unwrap_err();
+        assert!(error.to_string().contains("executable file not found"));
+    }
+
+    #[test]
+    fn websocket_connector_test() {
+        let client = HttpClient::new(HttpKubeConfig {
+            server: "https://127.0.0.1:6443".to_string(),
+            proxy_url: "http://proxy.local:3128".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        // 创建客户端时不加载 WebSocket 的 TLS 配置
+        assert!(client.websocket.get().is_none());
+        let Err(error) = client.exec(None, "nginx-0", &["echo"], &ExecParams::new()) else {
+            panic!("exec through a proxy should fail");
+        };
+        assert!(error.to_string().contains("proxy-url"));
+        assert!(client.websocket.get().is_some());
+    }
+
+    #[test]
+    fn connect_timeout_test() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(connect_timeout("localhost", port).is_ok());
+        drop(listener);
+        assert!(connect_timeout("127.0.0.1", port).is_err());
+    }
+}
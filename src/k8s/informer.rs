@@ -0,0 +1,394 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::{ListableResource, Metadata, Resource};
+use serde::de::DeserializeOwned;
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::watch::Event;
+
+/// 对象在 [`Store`] 中的键, 命名空间资源显示为 `namespace/name`
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectKey {
+    pub namespace: Option<String>,
+    pub name: String,
+}
+
+impl ObjectKey {
+    pub fn new(namespace: Option<&str>, name: &str) -> Self {
+        ObjectKey {
+            namespace: namespace.map(String::from),
+            name: name.to_string(),
+        }
+    }
+
+    pub fn of<K: Metadata<Ty = ObjectMeta>>(object: &K) -> Self {
+        let metadata = object.metadata();
+        ObjectKey {
+            namespace: metadata.namespace.clone(),
+            name: metadata.name.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for ObjectKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.namespace {
+            Some(namespace) => write!(f, "{}/{}", namespace, self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// 线程安全的对象缓存, 按命名空间和标签建立索引, 克隆后共享同一份数据
+pub struct Store<K> {
+    inner: Arc<RwLock<Indexed<K>>>,
+}
+
+struct Indexed<K> {
+    objects: HashMap<ObjectKey, Arc<K>>,
+    namespaces: HashMap<String, BTreeSet<ObjectKey>>,
+    labels: HashMap<(String, String), BTreeSet<ObjectKey>>,
+}
+
+impl<K> Clone for Store<K> {
+    fn clone(&self) -> Self {
+        Store {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K> Default for Store<K> {
+    fn default() -> Self {
+        Store {
+            inner: Arc::new(RwLock::new(Indexed {
+                objects: HashMap::new(),
+                namespaces: HashMap::new(),
+                labels: HashMap::new(),
+            })),
+        }
+    }
+}
+
+impl<K: Metadata<Ty = ObjectMeta>> Store<K> {
+    pub fn get(&self, key: &ObjectKey) -> Option<Arc<K>> {
+        self.inner.read().unwrap().objects.get(key).cloned()
+    }
+
+    /// 所有对象, 按键排序
+    pub fn list(&self) -> Vec<Arc<K>> {
+        let inner = self.inner.read().unwrap();
+        let keys: BTreeSet<&ObjectKey> = inner.objects.keys().collect();
+        keys.into_iter()
+            .filter_map(|key| inner.objects.get(key).cloned())
+            .collect()
+    }
+
+    /// 命名空间中的对象
+    pub fn by_namespace(&self, namespace: &str) -> Vec<Arc<K>> {
+        let inner = self.inner.read().unwrap();
+        inner
+            .namespaces
+            .get(namespace)
+            .map(|keys| {
+                keys.iter()
+                    .filter_map(|key| inner.objects.get(key).cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 带有标签 `key=value` 的对象
+    pub fn by_label(&self, key: &str, value: &str) -> Vec<Arc<K>> {
+        let inner = self.inner.read().unwrap();
+        inner
+            .labels
+            .get(&(key.to_string(), value.to_string()))
+            .map(|keys| {
+                keys.iter()
+                    .filter_map(|key| inner.objects.get(key).cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 插入或更新对象, 返回其键
+    pub fn insert(&self, object: K) -> ObjectKey {
+        let key = ObjectKey::of(&object);
+        let mut inner = self.inner.write().unwrap();
+        inner.unindex(&key);
+        if let Some(namespace) = &key.namespace {
+            inner
+                .namespaces
+                .entry(namespace.clone())
+                .or_default()
+                .insert(key.clone());
+        }
+        for (name, value) in object.metadata().labels.iter().flatten() {
+            inner
+                .labels
+                .entry((name.clone(), value.clone()))
+                .or_default()
+                .insert(key.clone());
+        }
+        inner.objects.insert(key.clone(), Arc::new(object));
+        key
+    }
+
+    pub fn remove(&self, key: &ObjectKey) -> Option<Arc<K>> {
+        let mut inner = self.inner.write().unwrap();
+        inner.unindex(key);
+        inner.objects.remove(key)
+    }
+
+    /// 使用 list 的结果替换全部对象, 返回新增, 更新和被删除的所有键
+    pub fn replace(&self, objects: Vec<K>) -> Vec<ObjectKey> {
+        let mut stale: BTreeSet<ObjectKey> =
+            self.inner.read().unwrap().objects.keys().cloned().collect();
+        let mut changed = vec![];
+        for object in objects {
+            let key = self.insert(object);
+            stale.remove(&key);
+            changed.push(key);
+        }
+        for key in stale {
+            self.remove(&key);
+            changed.push(key);
+        }
+        changed
+    }
+}
+
+impl<K: Metadata<Ty = ObjectMeta>> Indexed<K> {
+    fn unindex(&mut self, key: &ObjectKey) {
+        let Some(object) = self.objects.get(key).cloned() else {
+            return;
+        };
+        if let Some(namespace) = &key.namespace {
+            if let Some(keys) = self.namespaces.get_mut(namespace) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.namespaces.remove(namespace);
+                }
+            }
+        }
+        for (name, value) in object.metadata().labels.iter().flatten() {
+            let label = (name.clone(), value.clone());
+            if let Some(keys) = self.labels.get_mut(&label) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.labels.remove(&label);
+                }
+            }
+        }
+    }
+}
+
+/// 通过 list + watch 将资源同步到 [`Store`] 的 informer, 克隆后共享同一个 store 和停止标志.
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use k8s_openapi::api::core::v1::Pod;
+/// use rust_notes::k8s::api::HttpClient;
+/// use rust_notes::k8s::informer::Informer;
+/// use rust_notes::k8s::models::HttpKubeConfig;
+///
+/// let client = Arc::new(HttpClient::new(HttpKubeConfig::infer().unwrap()).unwrap());
+/// let informer = Informer::<Pod>::new(client, Some("default"), &[]);
+/// let store = informer.store();
+/// let runner = informer.clone();
+/// std::thread::spawn(move || runner.run(|key| println!("changed: {}", key)));
+/// // ...
+/// println!("{} pods in default", store.by_namespace("default").len());
+/// informer.stop();
+/// ```
+pub struct Informer<K> {
+    client: Arc<HttpClient>,
+    namespace: Option<String>,
+    args: Vec<String>,
+    store: Store<K>,
+    synced: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl<K> Clone for Informer<K> {
+    fn clone(&self) -> Self {
+        Informer {
+            client: self.client.clone(),
+            namespace: self.namespace.clone(),
+            args: self.args.clone(),
+            store: self.store.clone(),
+            synced: self.synced.clone(),
+            stopped: self.stopped.clone(),
+        }
+    }
+}
+
+impl<K> Informer<K>
+where
+    K: Resource + ListableResource + Metadata<Ty = ObjectMeta> + DeserializeOwned,
+    K::Scope: 'static,
+{
+    /// 命名空间资源在 `namespace` 为 `None` 时同步所有命名空间
+    pub fn new(client: Arc<HttpClient>, namespace: Option<&str>, args: &[&str]) -> Self {
+        Informer {
+            client,
+            namespace: namespace.map(String::from),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            store: Store::default(),
+            synced: Arc::new(AtomicBool::new(false)),
+            stopped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn store(&self) -> Store<K> {
+        self.store.clone()
+    }
+
+    /// 是否已完成第一次 list
+    pub fn has_synced(&self) -> bool {
+        self.synced.load(Ordering::SeqCst)
+    }
+
+    /// 停止 [`Informer::run`], 在收到下一个事件或 watch 重连时生效
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// 阻塞运行, 更新 store 后对每个新增, 更新或删除的对象调用 `on_change`.
+    /// watch 出错时等待后自动重试, 直到调用 [`Informer::stop`].
+    pub fn run<F: FnMut(&ObjectKey)>(&self, mut on_change: F) {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        let watcher = self.client.watch::<K>(self.namespace.as_deref(), &args);
+        for event in watcher {
+            if self.is_stopped() {
+                return;
+            }
+            match event {
+                Ok(Event::Restarted(objects)) => {
+                    for key in self.store.replace(objects) {
+                        on_change(&key);
+                    }
+                    self.synced.store(true, Ordering::SeqCst);
+                }
+                Ok(Event::Added(object)) | Ok(Event::Modified(object)) => {
+                    on_change(&self.store.insert(object));
+                }
+                Ok(Event::Deleted(object)) => {
+                    let key = ObjectKey::of(&object);
+                    self.store.remove(&key);
+                    on_change(&key);
+                }
+                Ok(Event::Bookmark(_)) => {}
+                // Watcher 在重新连接前按指数退避等待
+                Err(e) => log::warn!("informer {}: {}", K::KIND, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::Pod;
+
+    use super::*;
+
+    fn pod(namespace: &str, name: &str, app: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(namespace.to_string()),
+                labels: Some(BTreeMap::from([("app".to_string(), app.to_string())])),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn names(pods: Vec<Arc<Pod>>) -> Vec<String> {
+        pods.iter()
+            .map(|pod| pod.metadata.name.clone().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn store_index_test() {
+        let store = Store::default();
+        store.insert(pod("default", "web-0", "web"));
+        store.insert(pod("default", "db-0", "db"));
+        store.insert(pod("prod", "web-0", "web"));
+        assert_eq!(store.len(), 3);
+        assert_eq!(names(store.by_namespace("default")), vec!["db-0", "web-0"]);
+        assert_eq!(store.by_label("app", "web").len(), 2);
+
+        // 更新标签后旧索引失效
+        store.insert(pod("default", "web-0", "api"));
+        assert_eq!(store.by_label("app", "web").len(), 1);
+        assert_eq!(store.by_label("app", "api").len(), 1);
+
+        let key = ObjectKey::new(Some("prod"), "web-0");
+        assert_eq!(key.to_string(), "prod/web-0");
+        assert!(store.remove(&key).is_some());
+        assert!(store.get(&key).is_none());
+        assert!(store.by_label("app", "web").is_empty());
+        assert!(store.by_namespace("prod").is_empty());
+    }
+
+    #[test]
+    fn store_replace_test() {
+        let store = Store::default();
+        store.insert(pod("default", "old", "web"));
+        let changed = store.replace(vec![pod("default", "new", "web")]);
+        assert_eq!(
+            changed,
+            vec![
+                ObjectKey::new(Some("default"), "new"),
+                ObjectKey::new(Some("default"), "old")
+            ]
+        );
+        assert_eq!(names(store.list()), vec!["new"]);
+    }
+}
@@ -0,0 +1,508 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use kube::config::{
+    AuthInfo, Cluster, Context, Kubeconfig, NamedAuthInfo, NamedCluster, NamedContext,
+};
+use secrecy::SecretString;
+use serde_yaml::{Mapping, Value};
+
+use crate::k8s::error::K8sError;
+
+/// [`Kubeconfig`] 会序列化的字段, 其余字段在写回时原样保留
+const TOP_KEYS: &[&str] = &[
+    "preferences",
+    "clusters",
+    "users",
+    "contexts",
+    "current-context",
+    "extensions",
+    "kind",
+    "apiVersion",
+];
+const CLUSTER_KEYS: &[&str] = &[
+    "server",
+    "insecure-skip-tls-verify",
+    "certificate-authority",
+    "certificate-authority-data",
+    "proxy-url",
+    "tls-server-name",
+    "extensions",
+];
+const USER_KEYS: &[&str] = &[
+    "username",
+    "password",
+    "token",
+    "tokenFile",
+    "client-certificate",
+    "client-certificate-data",
+    "client-key",
+    "client-key-data",
+    "as",
+    "as-groups",
+    "auth-provider",
+    "exec",
+];
+const CONTEXT_KEYS: &[&str] = &["cluster", "user", "namespace", "extensions"];
+const EXEC_KEYS: &[&str] = &[
+    "apiVersion",
+    "command",
+    "args",
+    "env",
+    "interactiveMode",
+    "provideClusterInfo",
+];
+const AUTH_PROVIDER_KEYS: &[&str] = &["name", "config"];
+
+/// 基于 [`Kubeconfig`] 的 kubeconfig 编辑器.
+///
+/// 修改通过类型化的 [`KubeconfigEditor::config`] 进行, 写回 YAML 时按名称与原始内容合并,
+/// `Kubeconfig` 不认识的字段 (例如 `exec.installHint` 或自定义字段) 不会丢失.
+///
+/// ```
+/// use kube::config::Cluster;
+/// use rust_notes::k8s::kubeconfig::KubeconfigEditor;
+///
+/// let mut editor = KubeconfigEditor::new();
+/// editor.set_cluster("dev", Cluster {
+///     server: Some("https://10.0.0.1:6443".to_string()),
+///     ..Default::default()
+/// });
+/// assert!(editor.to_yaml().unwrap().contains("server: https://10.0.0.1:6443"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct KubeconfigEditor {
+    pub config: Kubeconfig,
+    raw: Value,
+    base_dir: Option<PathBuf>,
+}
+
+impl Default for KubeconfigEditor {
+    fn default() -> Self {
+        KubeconfigEditor::new()
+    }
+}
+
+impl KubeconfigEditor {
+    /// 空的 kubeconfig
+    pub fn new() -> Self {
+        KubeconfigEditor {
+            config: Kubeconfig {
+                api_version: Some("v1".to_string()),
+                kind: Some("Config".to_string()),
+                ..Default::default()
+            },
+            raw: Value::Mapping(Mapping::new()),
+            base_dir: None,
+        }
+    }
+
+    /// 解析 kubeconfig 文本, 证书文件的相对路径相对于当前工作目录
+    pub fn from_yaml(text: &str) -> Result<Self, K8sError> {
+        Ok(KubeconfigEditor {
+            config: Kubeconfig::from_yaml(text)?,
+            raw: serde_yaml::from_str(text).map_err(|e| K8sError::Decode(e.to_string()))?,
+            base_dir: None,
+        })
+    }
+
+    /// 读取 kubeconfig 文件, 文件中的相对路径保持不变
+    pub fn read_from<P: AsRef<Path>>(path: P) -> Result<Self, K8sError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| K8sError::Config(format!("read {}: {}", path.display(), e)))?;
+        let mut editor = KubeconfigEditor::from_yaml(&text)?;
+        editor.base_dir = path.parent().map(Path::to_path_buf);
+        Ok(editor)
+    }
+
+    /// 添加 cluster, 同名时替换
+    pub fn set_cluster(&mut self, name: &str, cluster: Cluster) {
+        let named = NamedCluster {
+            name: name.to_string(),
+            cluster: Some(cluster),
+        };
+        match self.config.clusters.iter_mut().find(|c| c.name == name) {
+            Some(existing) => *existing = named,
+            None => self.config.clusters.push(named),
+        }
+    }
+
+    /// 删除 cluster, 不存在时返回 `false`
+    pub fn remove_cluster(&mut self, name: &str) -> bool {
+        let len = self.config.clusters.len();
+        self.config.clusters.retain(|c| c.name != name);
+        len != self.config.clusters.len()
+    }
+
+    /// 添加 user, 同名时替换
+    pub fn set_user(&mut self, name: &str, user: AuthInfo) {
+        let named = NamedAuthInfo {
+            name: name.to_string(),
+            auth_info: Some(user),
+        };
+        match self.config.auth_infos.iter_mut().find(|u| u.name == name) {
+            Some(existing) => *existing = named,
+            None => self.config.auth_infos.push(named),
+        }
+    }
+
+    /// 删除 user, 不存在时返回 `false`
+    pub fn remove_user(&mut self, name: &str) -> bool {
+        let len = self.config.auth_infos.len();
+        self.config.auth_infos.retain(|u| u.name != name);
+        len != self.config.auth_infos.len()
+    }
+
+    /// 添加 context, 同名时替换
+    pub fn set_context(&mut self, name: &str, context: Context) {
+        let named = NamedContext {
+            name: name.to_string(),
+            context: Some(context),
+        };
+        match self.config.contexts.iter_mut().find(|c| c.name == name) {
+            Some(existing) => *existing = named,
+            None => self.config.contexts.push(named),
+        }
+    }
+
+    /// 删除 context, 删除的是 `current-context` 时一并清空, 不存在时返回 `false`
+    pub fn remove_context(&mut self, name: &str) -> bool {
+        let len = self.config.contexts.len();
+        self.config.contexts.retain(|c| c.name != name);
+        if self.config.current_context.as_deref() == Some(name) {
+            self.config.current_context = None;
+        }
+        len != self.config.contexts.len()
+    }
+
+    /// 设置 `current-context`, context 必须存在
+    pub fn set_current_context(&mut self, name: &str) -> Result<(), K8sError> {
+        self.context_index(name)?;
+        self.config.current_context = Some(name.to_string());
+        Ok(())
+    }
+
+    /// 重命名 context, 同时更新 `current-context`
+    pub fn rename_context(&mut self, from: &str, to: &str) -> Result<(), K8sError> {
+        let index = self.context_index(from)?;
+        if self.config.contexts.iter().any(|c| c.name == to) {
+            return Err(K8sError::Validation(format!(
+                "kubeconfig: context '{}' already exists",
+                to
+            )));
+        }
+        self.config.contexts[index].name = to.to_string();
+        if self.config.current_context.as_deref() == Some(from) {
+            self.config.current_context = Some(to.to_string());
+        }
+        // 同时重命名原始内容中的条目, 以便写回时保留其未知字段
+        if let Some(Value::Sequence(contexts)) = self.raw.get_mut("contexts") {
+            for context in contexts.iter_mut() {
+                if context.get("name").and_then(Value::as_str) == Some(from) {
+                    context["name"] = Value::from(to);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 将 `certificate-authority`, `client-certificate` 和 `client-key` 文件内容以 base64
+    /// 写入对应的 `*-data` 字段, 并删除文件路径, 生成的 kubeconfig 可以单独分发
+    pub fn embed_certs(&mut self) -> Result<(), K8sError> {
+        for named in self.config.clusters.iter_mut() {
+            if let Some(cluster) = named.cluster.as_mut() {
+                if let Some(path) = cluster.certificate_authority.take() {
+                    cluster.certificate_authority_data = Some(read_base64(&self.base_dir, &path)?);
+                }
+            }
+        }
+        for named in self.config.auth_infos.iter_mut() {
+            if let Some(user) = named.auth_info.as_mut() {
+                if let Some(path) = user.client_certificate.take() {
+                    user.client_certificate_data = Some(read_base64(&self.base_dir, &path)?);
+                }
+                if let Some(path) = user.client_key.take() {
+                    user.client_key_data =
+                        Some(SecretString::new(read_base64(&self.base_dir, &path)?));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 只保留一个 context 及其引用的 cluster 和 user, `context` 为 `None` 时使用 `current-context`
+    pub fn minify(&mut self, context: Option<&str>) -> Result<(), K8sError> {
+        let name = match context {
+            Some(name) => name.to_string(),
+            None => self.config.current_context.clone().ok_or_else(|| {
+                K8sError::Validation("kubeconfig: current-context is not set".into())
+            })?,
+        };
+        let index = self.context_index(&name)?;
+        let named = self.config.contexts[index].clone();
+        let context = named.context.clone().unwrap_or_default();
+        self.config.contexts = vec![named];
+        self.config.clusters.retain(|c| c.name == context.cluster);
+        self.config.auth_infos.retain(|u| u.name == context.user);
+        self.config.current_context = Some(name);
+        Ok(())
+    }
+
+    /// 序列化为 YAML, 保留原始内容中的未知字段
+    pub fn to_yaml(&self) -> Result<String, K8sError> {
+        let typed =
+            serde_yaml::to_value(&self.config).map_err(|e| K8sError::Decode(e.to_string()))?;
+        let (Value::Mapping(raw), Value::Mapping(typed)) = (&self.raw, &typed) else {
+            return serde_yaml::to_string(&typed).map_err(|e| K8sError::Decode(e.to_string()));
+        };
+        let mut merged = raw.clone();
+        for key in TOP_KEYS {
+            if !typed.contains_key(*key) {
+                merged.remove(*key);
+            }
+        }
+        for (key, value) in typed {
+            let value = match key.as_str() {
+                Some("clusters") => merge_named(raw.get(key), value, "cluster", CLUSTER_KEYS),
+                Some("users") => merge_named(raw.get(key), value, "user", USER_KEYS),
+                Some("contexts") => merge_named(raw.get(key), value, "context", CONTEXT_KEYS),
+                _ => value.clone(),
+            };
+            merged.insert(key.clone(), value);
+        }
+        serde_yaml::to_string(&merged).map_err(|e| K8sError::Decode(e.to_string()))
+    }
+
+    /// 写入文件, Unix 下文件权限为 0600
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> Result<(), K8sError> {
+        let path = path.as_ref();
+        let io_error =
+            |e: std::io::Error| K8sError::Config(format!("write {}: {}", path.display(), e));
+        std::fs::write(path, self.to_yaml()?).map_err(io_error)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                .map_err(io_error)?;
+        }
+        Ok(())
+    }
+
+    fn context_index(&self, name: &str) -> Result<usize, K8sError> {
+        self.config
+            .contexts
+            .iter()
+            .position(|c| c.name == name)
+            .ok_or_else(|| {
+                K8sError::Validation(format!("kubeconfig: context '{}' not found", name))
+            })
+    }
+}
+
+fn read_base64(base_dir: &Option<PathBuf>, path: &str) -> Result<String, K8sError> {
+    let path = match base_dir {
+        Some(base_dir) => base_dir.join(path),
+        None => PathBuf::from(path),
+    };
+    let data = std::fs::read(&path)
+        .map_err(|e| K8sError::Config(format!("kubeconfig: read {}: {}", path.display(), e)))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(data))
+}
+
+/// 按 `name` 合并 clusters/users/contexts 列表, 条目的顺序和内容以类型化的结果为准
+fn merge_named(raw: Option<&Value>, typed: &Value, field: &str, known: &[&str]) -> Value {
+    let (Some(Value::Sequence(raw)), Value::Sequence(typed)) = (raw, typed) else {
+        return typed.clone();
+    };
+    let merged = typed
+        .iter()
+        .map(|entry| {
+            let Some(original) = raw.iter().find(|r| r.get("name") == entry.get("name")) else {
+                return entry.clone();
+            };
+            let mut original = original.clone();
+            match entry.get(field) {
+                Some(value) => {
+                    original[field] = overlay(original.get(field), value, known);
+                }
+                None => {
+                    if let Value::Mapping(original) = &mut original {
+                        original.remove(field);
+                    }
+                }
+            }
+            original
+        })
+        .collect();
+    Value::Sequence(merged)
+}
+
+/// 将类型化的值合并到原始值上: `known` 中的字段以类型化的值为准, 其余字段保留
+fn overlay(raw: Option<&Value>, typed: &Value, known: &[&str]) -> Value {
+    let (Some(Value::Mapping(raw)), Value::Mapping(typed)) = (raw, typed) else {
+        return typed.clone();
+    };
+    let mut merged = raw.clone();
+    for key in known {
+        if !typed.contains_key(*key) {
+            merged.remove(*key);
+        }
+    }
+    for (key, value) in typed {
+        let value = match key.as_str() {
+            Some("exec") => overlay(raw.get(key), value, EXEC_KEYS),
+            Some("auth-provider") => overlay(raw.get(key), value, AUTH_PROVIDER_KEYS),
+            _ => value.clone(),
+        };
+        merged.insert(key.clone(), value);
+    }
+    Value::Mapping(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r###"apiVersion: v1
+kind: Config
+clusters:
+- cluster:
+    certificate-authority: pki/ca.crt
+    server: https://10.0.0.1:6443
+    x-team-note: keep me
+  name: dev
+- cluster:
+    server: https://10.0.0.2:6443
+  name: prod
+contexts:
+- context:
+    cluster: dev
+    user: dev-admin
+  name: dev
+  x-owner: team-a
+- context:
+    cluster: prod
+    user: prod-admin
+  name: prod
+current-context: dev
+users:
+- name: dev-admin
+  user:
+    client-certificate: pki/client.crt
+    client-key: pki/client.key
+- name: prod-admin
+  user:
+    exec:
+      apiVersion: client.authentication.k8s.io/v1beta1
+      command: aws
+      installHint: install the aws cli
+x-custom-top: 1
+"###;
+
+    fn reparse(editor: &KubeconfigEditor) -> Value {
+        serde_yaml::from_str(&editor.to_yaml().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn unknown_fields_test() {
+        let mut editor = KubeconfigEditor::from_yaml(CONFIG).unwrap();
+        editor.set_cluster(
+            "dev",
+            Cluster {
+                server: Some("https://10.0.0.10:6443".to_string()),
+                ..Default::default()
+            },
+        );
+        let value = reparse(&editor);
+        assert_eq!(value["x-custom-top"], Value::from(1));
+        let dev = &value["clusters"][0]["cluster"];
+        assert_eq!(dev["server"], Value::from("https://10.0.0.10:6443"));
+        assert_eq!(dev["x-team-note"], Value::from("keep me"));
+        // 被替换的 cluster 中没有 certificate-authority, 写回时删除
+        assert!(dev.get("certificate-authority").is_none());
+        assert_eq!(
+            value["users"][1]["user"]["exec"]["installHint"],
+            Value::from("install the aws cli")
+        );
+    }
+
+    #[test]
+    fn context_test() {
+        let mut editor = KubeconfigEditor::from_yaml(CONFIG).unwrap();
+        editor.rename_context("dev", "dev-admin@dev").unwrap();
+        assert!(editor.rename_context("prod", "dev-admin@dev").is_err());
+        assert!(editor.set_current_context("missing").is_err());
+        let value = reparse(&editor);
+        assert_eq!(value["current-context"], Value::from("dev-admin@dev"));
+        assert_eq!(value["contexts"][0]["x-owner"], Value::from("team-a"));
+
+        assert!(editor.remove_context("dev-admin@dev"));
+        assert!(!editor.remove_context("dev-admin@dev"));
+        assert!(editor.config.current_context.is_none());
+        editor.set_current_context("prod").unwrap();
+        assert!(editor.remove_user("dev-admin"));
+        assert!(editor.remove_cluster("dev"));
+        let value = reparse(&editor);
+        assert_eq!(value["clusters"].as_sequence().unwrap().len(), 1);
+        assert_eq!(value["users"].as_sequence().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn minify_test() {
+        let mut editor = KubeconfigEditor::from_yaml(CONFIG).unwrap();
+        editor.minify(Some("prod")).unwrap();
+        assert_eq!(editor.config.clusters.len(), 1);
+        assert_eq!(editor.config.auth_infos[0].name, "prod-admin");
+        assert_eq!(editor.config.current_context.as_deref(), Some("prod"));
+        assert!(editor.minify(Some("dev")).is_err());
+    }
+
+    #[test]
+    fn embed_certs_test() {
+        let dir = std::env::temp_dir().join(format!("rust-notes-editor-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("pki")).unwrap();
+        std::fs::write(dir.join("pki").join("ca.crt"), "ca").unwrap();
+        std::fs::write(dir.join("pki").join("client.crt"), "cert").unwrap();
+        std::fs::write(dir.join("pki").join("client.key"), "key").unwrap();
+        std::fs::write(dir.join("config"), CONFIG).unwrap();
+
+        let mut editor = KubeconfigEditor::read_from(dir.join("config")).unwrap();
+        editor.embed_certs().unwrap();
+        editor.write_to(dir.join("embedded")).unwrap();
+        let value: Value =
+            serde_yaml::from_str(&std::fs::read_to_string(dir.join("embedded")).unwrap()).unwrap();
+        let cluster = &value["clusters"][0]["cluster"];
+        assert_eq!(cluster["certificate-authority-data"], Value::from("Y2E="));
+        assert!(cluster.get("certificate-authority").is_none());
+        let user = &value["users"][0]["user"];
+        assert_eq!(user["client-certificate-data"], Value::from("Y2VydA=="));
+        assert_eq!(user["client-key-data"], Value::from("a2V5"));
+        assert!(user.get("client-key").is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
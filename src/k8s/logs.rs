@@ -0,0 +1,266 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+
+use k8s_openapi::api::core::v1::Pod;
+use reqwest::Method;
+
+use crate::k8s::api::{HttpClient, LineReader, STREAM_POLL_INTERVAL};
+use crate::k8s::error::K8sError;
+use crate::k8s::params::{encode, LabelSelector, ListParams};
+use crate::k8s::resource::resource_path;
+
+/// 日志查询参数, 对应 `/api/v1/namespaces/{namespace}/pods/{name}/log` 的查询参数
+///
+/// ```
+/// use rust_notes::k8s::logs::LogParams;
+///
+/// let params = LogParams::new().container("nginx").tail_lines(100).timestamps();
+/// assert_eq!(params.query(), "container=nginx&tailLines=100&timestamps=true");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LogParams {
+    pub container: Option<String>,
+    pub follow: bool,
+    pub tail_lines: Option<i64>,
+    pub since_seconds: Option<i64>,
+    pub timestamps: bool,
+    pub previous: bool,
+}
+
+impl LogParams {
+    pub fn new() -> Self {
+        LogParams::default()
+    }
+
+    /// 容器名称, Pod 中只有一个容器时可以省略
+    pub fn container(mut self, container: &str) -> Self {
+        self.container = Some(container.to_string());
+        self
+    }
+
+    /// 持续输出新日志, 类似 `kubectl logs -f`
+    pub fn follow(mut self) -> Self {
+        self.follow = true;
+        self
+    }
+
+    pub fn tail_lines(mut self, tail_lines: i64) -> Self {
+        self.tail_lines = Some(tail_lines);
+        self
+    }
+
+    pub fn since_seconds(mut self, since_seconds: i64) -> Self {
+        self.since_seconds = Some(since_seconds);
+        self
+    }
+
+    /// 每行日志前添加 RFC3339 时间戳
+    pub fn timestamps(mut self) -> Self {
+        self.timestamps = true;
+        self
+    }
+
+    /// 查询上一次终止的容器的日志
+    pub fn previous(mut self) -> Self {
+        self.previous = true;
+        self
+    }
+
+    /// 生成编码后的查询字符串
+    pub fn query(&self) -> String {
+        let mut pairs: Vec<(&str, String)> = vec![];
+        if let Some(container) = &self.container {
+            pairs.push(("container", container.to_string()));
+        }
+        if self.follow {
+            pairs.push(("follow", "true".to_string()));
+        }
+        if let Some(tail_lines) = self.tail_lines {
+            pairs.push(("tailLines", tail_lines.to_string()));
+        }
+        if let Some(since_seconds) = self.since_seconds {
+            pairs.push(("sinceSeconds", since_seconds.to_string()));
+        }
+        if self.timestamps {
+            pairs.push(("timestamps", "true".to_string()));
+        }
+        if self.previous {
+            pairs.push(("previous", "true".to_string()));
+        }
+        pairs
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, encode(value)))
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+}
+
+/// 按行读取日志的阻塞迭代器, 不会缓存整个响应体.
+///
+/// 没有总的超时时间, `follow` 模式下直到日志流被 apiserver 关闭或迭代器被丢弃
+pub struct LogStream {
+    lines: LineReader,
+}
+
+impl LogStream {
+    /// 读取下一行, 等待期间 `stopped` 为 `true` 时返回 `None`
+    fn next_until(&mut self, stopped: &AtomicBool) -> Option<Result<String, K8sError>> {
+        self.lines
+            .next_line(|| stopped.load(Ordering::Relaxed))
+            .map(|line| line.map_err(|e| K8sError::Decode(format!("read log: {}", e))))
+    }
+}
+
+impl Iterator for LogStream {
+    type Item = Result<String, K8sError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_until(&AtomicBool::new(false))
+    }
+}
+
+impl HttpClient {
+    /// 查询 Pod 日志, `namespace` 为 `None` 时使用 context 的默认命名空间
+    pub fn logs(
+        &self,
+        namespace: Option<&str>,
+        name: &str,
+        params: &LogParams,
+    ) -> Result<LogStream, K8sError> {
+        let namespace = namespace.unwrap_or(self.default_namespace());
+        let path = resource_path::<Pod>(Some(namespace), Some(name));
+        let url = self.url(&[&path, "log"], &[&params.query()]);
+        let request = self
+            .request(Method::GET, &url)?
+            .timeout(STREAM_POLL_INTERVAL);
+        let response = self.send(request)?;
+        Ok(LogStream {
+            lines: LineReader::new(response),
+        })
+    }
+
+    /// 合并标签选择器匹配的所有 Pod 的日志, 每行日志以 `[pod-name] ` 为前缀传给 `on_line`.
+    ///
+    /// 每个 Pod 使用一个线程读取日志, `follow` 模式下直到所有日志流结束, 或者 `on_line` 返回 `false` 时停止,
+    /// 停止后几秒内所有读取线程结束并返回.
+    /// 单个 Pod 的错误出现时以 `[pod-name] error: ...` 的形式传给 `on_line`, 不影响其他 Pod,
+    /// 结束时返回第一个出现的错误.
+    pub fn selector_logs<F>(
+        &self,
+        namespace: Option<&str>,
+        selector: &LabelSelector,
+        params: &LogParams,
+        mut on_line: F,
+    ) -> Result<(), K8sError>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let namespace = namespace.unwrap_or(self.default_namespace());
+        let query = ListParams::new().labels(selector.clone()).query()?;
+        let pods = self.list::<Pod>(Some(namespace), &[&query])?;
+        let names: Vec<String> = pods
+            .items
+            .into_iter()
+            .filter_map(|pod| pod.metadata.name)
+            .collect();
+
+        let (sender, receiver) = mpsc::channel::<(&str, Result<String, K8sError>)>();
+        let stopped = AtomicBool::new(false);
+        let mut first_error = None;
+        std::thread::scope(|scope| {
+            for name in &names {
+                let sender = sender.clone();
+                let stopped = &stopped;
+                scope.spawn(move || {
+                    let mut lines = match self.logs(Some(namespace), name, params) {
+                        Ok(lines) => lines,
+                        Err(e) => {
+                            let _ = sender.send((name, Err(e)));
+                            return;
+                        }
+                    };
+                    while let Some(line) = lines.next_until(stopped) {
+                        // 读取出错后日志流不能继续
+                        let failed = line.is_err();
+                        if sender.send((name, line)).is_err() || failed {
+                            break;
+                        }
+                    }
+                });
+            }
+            // 所有读取线程结束后 channel 关闭, 接收循环随之结束
+            drop(sender);
+            for (name, line) in receiver {
+                let next = match line {
+                    Ok(line) => on_line(&prefix(name, &line)),
+                    Err(e) => {
+                        let next = on_line(&prefix(name, &format!("error: {}", e)));
+                        first_error.get_or_insert(e);
+                        next
+                    }
+                };
+                if !next {
+                    // 丢弃 receiver 后读取线程的发送失败, 正在等待的线程检查 stopped 后结束
+                    stopped.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+        });
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+fn prefix(pod: &str, line: &str) -> String {
+    format!("[{}] {}", pod, line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_test() {
+        assert_eq!(LogParams::new().query(), "");
+        let params = LogParams::new()
+            .container("istio proxy")
+            .follow()
+            .since_seconds(60)
+            .previous();
+        assert_eq!(
+            params.query(),
+            "container=istio%20proxy&follow=true&sinceSeconds=60&previous=true"
+        );
+    }
+
+    #[test]
+    fn prefix_test() {
+        assert_eq!(prefix("nginx-0", "GET / 200"), "[nginx-0] GET / 200");
+    }
+}
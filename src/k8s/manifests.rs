@@ -0,0 +1,403 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use kube::core::DynamicObject;
+use serde::Deserialize;
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::error::K8sError;
+use crate::k8s::patch::{Patch, PatchParams};
+
+/// 按依赖顺序排在前面的资源类型, 其余资源保持文件中的顺序
+const KIND_ORDER: &[&str] = &[
+    "Namespace",
+    "CustomResourceDefinition",
+    "PriorityClass",
+    "StorageClass",
+    "ServiceAccount",
+    "Secret",
+    "ConfigMap",
+    "PersistentVolume",
+    "PersistentVolumeClaim",
+    "ClusterRole",
+    "ClusterRoleBinding",
+    "Role",
+    "RoleBinding",
+    "Service",
+];
+
+/// 等待新创建的 CRD 生效的最大次数, 每次间隔 1 秒
+const CRD_ESTABLISH_ATTEMPTS: u32 = 10;
+
+/// [`apply_manifests`] 的参数
+#[derive(Clone, Debug)]
+pub struct ApplyOptions {
+    /// Server-Side Apply 使用的 field manager
+    pub field_manager: String,
+
+    /// 清单中没有指定命名空间的对象使用的命名空间, 为 `None` 时使用 context 的默认命名空间
+    pub namespace: Option<String>,
+
+    /// 与其它 field manager 冲突时强制覆盖
+    pub force: bool,
+
+    /// 只在服务端校验, 不持久化
+    pub dry_run: bool,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> Self {
+        ApplyOptions {
+            field_manager: "rust-notes".to_string(),
+            namespace: None,
+            force: false,
+            dry_run: false,
+        }
+    }
+}
+
+/// 单个对象的 apply 结果
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApplyAction {
+    Created,
+    Configured,
+    Unchanged,
+    /// dry run 时对象的 CRD 也在本次 apply 中且集群中还没有, 无法在服务端校验, 没有发送请求
+    Unverified,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApplyResult {
+    pub kind: String,
+    pub group: String,
+    pub namespace: Option<String>,
+    pub name: String,
+    pub action: ApplyAction,
+}
+
+impl fmt::Display for ApplyResult {
+    /// 与 kubectl 相同的输出格式, 例如 `deployment.apps/nginx configured`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            ApplyAction::Created => "created",
+            ApplyAction::Configured => "configured",
+            ApplyAction::Unchanged => "unchanged",
+            ApplyAction::Unverified => "unverified (dry run)",
+        };
+        let kind = self.kind.to_lowercase();
+        if self.group.is_empty() {
+            write!(f, "{}/{} {}", kind, self.name, action)
+        } else {
+            write!(f, "{}.{}/{} {}", kind, self.group, self.name, action)
+        }
+    }
+}
+
+/// 读取 YAML/JSON 文件或目录 (不递归) 中的所有对象, 支持多文档 YAML 和 `kind: List`
+pub fn read_manifests<P: AsRef<Path>>(path: P) -> Result<Vec<DynamicObject>, K8sError> {
+    let path = path.as_ref();
+    let files: Vec<PathBuf> = if path.is_dir() {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+            .map_err(|e| K8sError::Config(format!("read {}: {}", path.display(), e)))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| {
+                file.is_file()
+                    && matches!(
+                        file.extension().and_then(|ext| ext.to_str()),
+                        Some("yaml" | "yml" | "json")
+                    )
+            })
+            .collect();
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+    let mut objects = vec![];
+    for file in files {
+        let text = std::fs::read_to_string(&file)
+            .map_err(|e| K8sError::Config(format!("read {}: {}", file.display(), e)))?;
+        objects.extend(
+            parse_manifests(&text)
+                .map_err(|e| K8sError::Decode(format!("{}: {}", file.display(), e)))?,
+        );
+    }
+    Ok(objects)
+}
+
+/// 解析多文档 YAML, 跳过空文档并展开 `kind: List`
+pub fn parse_manifests(text: &str) -> Result<Vec<DynamicObject>, K8sError> {
+    let mut objects = vec![];
+    for document in serde_yaml::Deserializer::from_str(text) {
+        let value = serde_yaml::Value::deserialize(document)
+            .map_err(|e| K8sError::Decode(e.to_string()))?;
+        if value.is_null() {
+            continue;
+        }
+        let object: DynamicObject =
+            serde_yaml::from_value(value).map_err(|e| K8sError::Decode(e.to_string()))?;
+        if object.types.as_ref().map(|types| types.kind.as_str()) == Some("List") {
+            let items = object.data["items"].as_array().cloned().unwrap_or_default();
+            for item in items {
+                objects.push(serde_json::from_value(item)?);
+            }
+            continue;
+        }
+        objects.push(object);
+    }
+    for object in &objects {
+        if object.types.is_none() || object.metadata.name.is_none() {
+            return Err(K8sError::Validation(format!(
+                "manifest object {:?} requires apiVersion, kind and metadata.name",
+                object.metadata.name
+            )));
+        }
+    }
+    Ok(objects)
+}
+
+/// 按依赖顺序排序, Namespace 和 CRD 排在最前面
+pub fn sort_manifests(objects: &mut [DynamicObject]) {
+    objects.sort_by_key(|object| {
+        let kind = object.types.as_ref().map(|types| types.kind.as_str());
+        KIND_ORDER
+            .iter()
+            .position(|k| Some(*k) == kind)
+            .unwrap_or(KIND_ORDER.len())
+    });
+}
+
+/// 类似 `kubectl apply -f`, 使用 Server-Side Apply 按依赖顺序应用文件或目录中的所有对象
+pub fn apply_manifests<P: AsRef<Path>>(
+    client: &HttpClient,
+    path: P,
+    options: &ApplyOptions,
+) -> Result<Vec<ApplyResult>, K8sError> {
+    let mut objects = read_manifests(path)?;
+    sort_manifests(&mut objects);
+    let mut results = vec![];
+    // 本次创建的 CRD 定义的 (group, kind)
+    let mut defined = vec![];
+    for object in objects {
+        let types = object.types.clone().unwrap_or_default();
+        if types.kind == "CustomResourceDefinition" {
+            let spec = &object.data["spec"];
+            if let (Some(group), Some(kind)) =
+                (spec["group"].as_str(), spec["names"]["kind"].as_str())
+            {
+                defined.push((group.to_string(), kind.to_string()));
+            }
+        }
+        let group = types
+            .api_version
+            .rsplit_once('/')
+            .map_or("", |(group, _)| group);
+        if defined.contains(&(group.to_string(), types.kind.clone())) {
+            if !options.dry_run {
+                wait_for_resource(client, &types.api_version, &types.kind)?;
+            } else if client
+                .refresh_group_version(&types.api_version)?
+                .resolve(&types.api_version, &types.kind)
+                .is_none()
+            {
+                // dry run 不会创建 CRD, 不需要等待
+                results.push(ApplyResult {
+                    group: group.to_string(),
+                    namespace: object.metadata.namespace.clone(),
+                    name: object.metadata.name.clone().unwrap_or_default(),
+                    kind: types.kind,
+                    action: ApplyAction::Unverified,
+                });
+                continue;
+            }
+        }
+        results.push(apply_object(client, object, options)?);
+    }
+    Ok(results)
+}
+
+pub(crate) fn apply_object(
+    client: &HttpClient,
+    mut object: DynamicObject,
+    options: &ApplyOptions,
+) -> Result<ApplyResult, K8sError> {
+    let types = object.types.clone().unwrap_or_default();
+    let resource = client.resolve(&types.api_version, &types.kind)?;
+    let name = object.metadata.name.clone().unwrap_or_default();
+    let namespace = if resource.namespaced {
+        let namespace = object
+            .metadata
+            .namespace
+            .clone()
+            .or_else(|| options.namespace.clone())
+            .unwrap_or_else(|| client.default_namespace().to_string());
+        object.metadata.namespace = Some(namespace.clone());
+        Some(namespace)
+    } else {
+        None
+    };
+
+    let before =
+        match client.get_dynamic(&types.api_version, &types.kind, namespace.as_deref(), &name) {
+            Ok(existing) => existing.metadata.resource_version,
+            Err(e) if e.is_not_found() => None,
+            Err(e) => return Err(e),
+        };
+    let mut params = PatchParams::apply(&options.field_manager);
+    params.force = options.force;
+    params.dry_run = options.dry_run;
+    let applied = client.patch_dynamic(
+        &resource,
+        namespace.as_deref(),
+        &name,
+        &params,
+        &Patch::Apply(&object),
+    )?;
+    let action = match before {
+        None => ApplyAction::Created,
+        Some(version) if Some(&version) == applied.metadata.resource_version.as_ref() => {
+            ApplyAction::Unchanged
+        }
+        Some(_) => ApplyAction::Configured,
+    };
+    Ok(ApplyResult {
+        kind: types.kind,
+        group: resource.group,
+        namespace,
+        name,
+        action,
+    })
+}
+
+/// 刚刚创建的 CRD 可能还没有生效, 等待 discovery 中出现该资源, 每次只刷新该 group/version
+fn wait_for_resource(client: &HttpClient, api_version: &str, kind: &str) -> Result<(), K8sError> {
+    for _ in 0..CRD_ESTABLISH_ATTEMPTS {
+        if client
+            .refresh_group_version(api_version)?
+            .resolve(api_version, kind)
+            .is_some()
+        {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    Err(K8sError::Validation(format!(
+        "no resource found for apiVersion '{}' kind '{}' after its CustomResourceDefinition was applied",
+        api_version, kind
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFESTS: &str = r###"
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: nginx
+  namespace: demo
+---
+# 空文档会被忽略
+---
+apiVersion: v1
+kind: List
+items:
+- apiVersion: v1
+  kind: Service
+  metadata:
+    name: nginx
+    namespace: demo
+- apiVersion: v1
+  kind: ConfigMap
+  metadata:
+    name: nginx
+    namespace: demo
+---
+apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: crontabs.stable.example.com
+---
+apiVersion: v1
+kind: Namespace
+metadata:
+  name: demo
+"###;
+
+    fn kinds(objects: &[DynamicObject]) -> Vec<String> {
+        objects
+            .iter()
+            .map(|object| object.types.clone().unwrap().kind)
+            .collect()
+    }
+
+    #[test]
+    fn parse_and_sort_test() {
+        let mut objects = parse_manifests(MANIFESTS).unwrap();
+        assert_eq!(
+            kinds(&objects),
+            vec![
+                "Deployment",
+                "Service",
+                "ConfigMap",
+                "CustomResourceDefinition",
+                "Namespace"
+            ]
+        );
+        sort_manifests(&mut objects);
+        assert_eq!(
+            kinds(&objects),
+            vec![
+                "Namespace",
+                "CustomResourceDefinition",
+                "ConfigMap",
+                "Service",
+                "Deployment"
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_manifest_test() {
+        assert!(parse_manifests("apiVersion: v1\nkind: ConfigMap\n").is_err());
+        assert!(parse_manifests("metadata:\n  name: demo\n").is_err());
+    }
+
+    #[test]
+    fn apply_result_test() {
+        let result = ApplyResult {
+            kind: "Deployment".to_string(),
+            group: "apps".to_string(),
+            namespace: Some("demo".to_string()),
+            name: "nginx".to_string(),
+            action: ApplyAction::Configured,
+        };
+        assert_eq!(result.to_string(), "deployment.apps/nginx configured");
+    }
+}
@@ -22,5 +22,27 @@
  * SOFTWARE.
  */
 
+pub mod access;
 pub mod api;
+pub mod async_api;
+pub mod auth;
+pub mod backup;
+pub mod controller;
+pub mod discovery;
+pub mod error;
+pub mod events;
+pub mod exec;
+pub mod informer;
+pub mod kubeconfig;
+pub mod logs;
+pub mod manifests;
 pub mod models;
+pub mod pager;
+pub mod params;
+pub mod patch;
+pub mod report;
+pub mod resource;
+pub mod retry;
+pub mod service_account;
+pub mod tls;
+pub mod watch;
@@ -0,0 +1,161 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::VecDeque;
+
+use k8s_openapi::{List, ListableResource, Resource};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::error::{ApiError, K8sError};
+use crate::k8s::resource::resource_path;
+
+/// 默认的分页大小
+pub const DEFAULT_PAGE_SIZE: u32 = 500;
+
+/// 通过 `limit` 和 `metadata.continue` 分页查询资源的阻塞迭代器, 逐个返回对象.
+///
+/// `continue` token 过期 (`410 Gone`) 时默认返回错误,
+/// 开启 [`Pager::restart_on_expired`] 后会从第一页重新查询, 已返回的对象可能会重复出现.
+pub struct Pager<'a, K> {
+    client: &'a HttpClient,
+    namespace: Option<String>,
+    args: Vec<String>,
+    page_size: u32,
+    restart_on_expired: bool,
+    continue_token: Option<String>,
+    items: VecDeque<K>,
+    done: bool,
+}
+
+impl HttpClient {
+    /// 分页查询全部资源, 命名空间资源在 `namespace` 为 `None` 时查询所有命名空间.
+    /// `args` 中的 `limit` 作为分页大小, 默认为 [`DEFAULT_PAGE_SIZE`]
+    pub fn list_all<K>(&self, namespace: Option<&str>, args: &[&str]) -> Pager<'_, K> {
+        let mut page_size = DEFAULT_PAGE_SIZE;
+        let args = args
+            .iter()
+            .map(|arg| {
+                // `limit` 由 Pager 在每次请求时添加, 参数可能是 `a=1&limit=2` 的形式
+                arg.split('&')
+                    .filter(|pair| match pair.strip_prefix("limit=") {
+                        Some(limit) => {
+                            page_size = limit.parse().unwrap_or(page_size);
+                            false
+                        }
+                        None => true,
+                    })
+                    .collect::<Vec<&str>>()
+                    .join("&")
+            })
+            .filter(|arg| !arg.is_empty())
+            .collect();
+        Pager {
+            client: self,
+            namespace: namespace.map(String::from),
+            args,
+            page_size,
+            restart_on_expired: false,
+            continue_token: None,
+            items: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+impl<'a, K> Pager<'a, K>
+where
+    K: Resource + ListableResource + DeserializeOwned,
+    K::Scope: 'static,
+{
+    /// 每页的对象数量
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// `continue` token 过期时从第一页重新查询, 而不是返回错误
+    pub fn restart_on_expired(mut self, restart: bool) -> Self {
+        self.restart_on_expired = restart;
+        self
+    }
+
+    fn fetch(&mut self) -> Result<(), K8sError> {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        let path = resource_path::<K>(self.namespace.as_deref(), None);
+        let url = self.client.url(&[&path], &args);
+        let mut request = self
+            .client
+            .request(Method::GET, &url)?
+            .query(&[("limit", self.page_size.to_string())]);
+        if let Some(token) = &self.continue_token {
+            request = request.query(&[("continue", token)]);
+        }
+        let response = self.client.execute(request)?;
+        if response.status() == StatusCode::GONE && self.continue_token.is_some() {
+            if self.restart_on_expired {
+                log::debug!("list continue token expired, restart from the first page");
+                self.continue_token = None;
+                return Ok(());
+            }
+            let code = response.status();
+            let mut error = ApiError::from_response(code, &response.text().unwrap_or_default());
+            error.message = format!(
+                "list {}: continue token expired, the list must be restarted: {}",
+                K::URL_PATH_SEGMENT,
+                error.message
+            );
+            return Err(K8sError::Api(error));
+        }
+        let list: List<K> = HttpClient::check(response)?.json()?;
+        self.items.extend(list.items);
+        self.continue_token = list.metadata.continue_.filter(|token| !token.is_empty());
+        self.done = self.continue_token.is_none();
+        Ok(())
+    }
+}
+
+impl<'a, K> Iterator for Pager<'a, K>
+where
+    K: Resource + ListableResource + DeserializeOwned,
+    K::Scope: 'static,
+{
+    type Item = Result<K, K8sError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.items.pop_front() {
+                return Some(Ok(item));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(e) = self.fetch() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
@@ -0,0 +1,389 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::fmt;
+
+use crate::k8s::error::K8sError;
+
+/// 标签选择器的单个表达式, 参考 [Labels and Selectors](https://kubernetes.io/docs/concepts/overview/working-with-objects/labels/)
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    /// `key=value`
+    Equal(String, String),
+    /// `key!=value`
+    NotEqual(String, String),
+    /// `key in (v1,v2)`
+    In(String, Vec<String>),
+    /// `key notin (v1,v2)`
+    NotIn(String, Vec<String>),
+    /// `key`
+    Exists(String),
+    /// `!key`
+    DoesNotExist(String),
+}
+
+impl Expression {
+    fn validate(&self) -> Result<(), K8sError> {
+        match self {
+            Expression::Equal(key, value) | Expression::NotEqual(key, value) => {
+                validate_label_key(key)?;
+                validate_label_value(value)
+            }
+            Expression::In(key, values) | Expression::NotIn(key, values) => {
+                validate_label_key(key)?;
+                if values.is_empty() {
+                    return Err(K8sError::Validation(format!(
+                        "label selector: '{}' requires at least one value",
+                        key
+                    )));
+                }
+                values
+                    .iter()
+                    .try_for_each(|value| validate_label_value(value))
+            }
+            Expression::Exists(key) | Expression::DoesNotExist(key) => validate_label_key(key),
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Equal(key, value) => write!(f, "{}={}", key, value),
+            Expression::NotEqual(key, value) => write!(f, "{}!={}", key, value),
+            Expression::In(key, values) => write!(f, "{} in ({})", key, values.join(",")),
+            Expression::NotIn(key, values) => write!(f, "{} notin ({})", key, values.join(",")),
+            Expression::Exists(key) => write!(f, "{}", key),
+            Expression::DoesNotExist(key) => write!(f, "!{}", key),
+        }
+    }
+}
+
+/// 标签选择器, 多个表达式之间是 AND 关系
+///
+/// ```
+/// use rust_notes::k8s::params::LabelSelector;
+///
+/// let selector = LabelSelector::new().eq("tier", "control-plane").exists("component");
+/// assert_eq!(selector.to_string(), "tier=control-plane,component");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LabelSelector {
+    expressions: Vec<Expression>,
+}
+
+impl LabelSelector {
+    pub fn new() -> Self {
+        LabelSelector::default()
+    }
+
+    pub fn eq(self, key: &str, value: &str) -> Self {
+        self.with(Expression::Equal(key.to_string(), value.to_string()))
+    }
+
+    pub fn ne(self, key: &str, value: &str) -> Self {
+        self.with(Expression::NotEqual(key.to_string(), value.to_string()))
+    }
+
+    pub fn in_values(self, key: &str, values: &[&str]) -> Self {
+        let values = values.iter().map(|value| value.to_string()).collect();
+        self.with(Expression::In(key.to_string(), values))
+    }
+
+    pub fn not_in(self, key: &str, values: &[&str]) -> Self {
+        let values = values.iter().map(|value| value.to_string()).collect();
+        self.with(Expression::NotIn(key.to_string(), values))
+    }
+
+    pub fn exists(self, key: &str) -> Self {
+        self.with(Expression::Exists(key.to_string()))
+    }
+
+    pub fn not_exists(self, key: &str) -> Self {
+        self.with(Expression::DoesNotExist(key.to_string()))
+    }
+
+    pub fn with(mut self, expression: Expression) -> Self {
+        self.expressions.push(expression);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expressions.is_empty()
+    }
+
+    /// 按 Kubernetes 的规则校验标签的键和值
+    pub fn validate(&self) -> Result<(), K8sError> {
+        self.expressions.iter().try_for_each(Expression::validate)
+    }
+}
+
+impl fmt::Display for LabelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expressions: Vec<String> = self.expressions.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", expressions.join(","))
+    }
+}
+
+/// 字段选择器, 例如 `status.phase=Running`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FieldSelector {
+    expressions: Vec<String>,
+}
+
+impl FieldSelector {
+    pub fn new() -> Self {
+        FieldSelector::default()
+    }
+
+    pub fn eq(mut self, field: &str, value: &str) -> Self {
+        self.expressions.push(format!("{}={}", field, value));
+        self
+    }
+
+    pub fn ne(mut self, field: &str, value: &str) -> Self {
+        self.expressions.push(format!("{}!={}", field, value));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expressions.is_empty()
+    }
+}
+
+impl fmt::Display for FieldSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expressions.join(","))
+    }
+}
+
+/// list/watch 请求的查询参数
+///
+/// ```
+/// use rust_notes::k8s::params::{LabelSelector, ListParams};
+///
+/// let params = ListParams::new()
+///     .labels(LabelSelector::new().eq("tier", "control-plane"))
+///     .limit(500);
+/// assert_eq!(params.query().unwrap(), "labelSelector=tier%3Dcontrol-plane&limit=500");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ListParams {
+    pub label_selector: LabelSelector,
+    pub field_selector: FieldSelector,
+    pub limit: Option<u32>,
+    pub timeout_seconds: Option<u32>,
+    pub resource_version: Option<String>,
+}
+
+impl ListParams {
+    pub fn new() -> Self {
+        ListParams::default()
+    }
+
+    pub fn labels(mut self, selector: LabelSelector) -> Self {
+        self.label_selector = selector;
+        self
+    }
+
+    pub fn fields(mut self, selector: FieldSelector) -> Self {
+        self.field_selector = selector;
+        self
+    }
+
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn timeout(mut self, timeout_seconds: u32) -> Self {
+        self.timeout_seconds = Some(timeout_seconds);
+        self
+    }
+
+    pub fn resource_version(mut self, resource_version: &str) -> Self {
+        self.resource_version = Some(resource_version.to_string());
+        self
+    }
+
+    /// 校验标签选择器并生成编码后的查询字符串, 可以直接作为 `args` 传给 [`HttpClient`][crate::k8s::api::HttpClient] 的方法
+    pub fn query(&self) -> Result<String, K8sError> {
+        self.label_selector.validate()?;
+        let mut pairs: Vec<(&str, String)> = vec![];
+        if !self.label_selector.is_empty() {
+            pairs.push(("labelSelector", self.label_selector.to_string()));
+        }
+        if !self.field_selector.is_empty() {
+            pairs.push(("fieldSelector", self.field_selector.to_string()));
+        }
+        if let Some(limit) = self.limit {
+            pairs.push(("limit", limit.to_string()));
+        }
+        if let Some(timeout_seconds) = self.timeout_seconds {
+            pairs.push(("timeoutSeconds", timeout_seconds.to_string()));
+        }
+        if let Some(resource_version) = &self.resource_version {
+            pairs.push(("resourceVersion", resource_version.to_string()));
+        }
+        Ok(pairs
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, encode(value)))
+            .collect::<Vec<String>>()
+            .join("&"))
+    }
+}
+
+/// 对查询参数的值进行百分号编码, 只保留 RFC 3986 中的非保留字符
+pub fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// 标签的键: 可选的 DNS 子域名前缀加 `/`, 以及不超过 63 个字符的名称
+pub fn validate_label_key(key: &str) -> Result<(), K8sError> {
+    let (prefix, name) = match key.split_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+    if let Some(prefix) = prefix {
+        if !is_dns_subdomain(prefix) {
+            return Err(K8sError::Validation(format!(
+                "invalid label key '{}': prefix must be a DNS subdomain of at most 253 characters",
+                key
+            )));
+        }
+    }
+    if name.is_empty() || !is_qualified_name(name) {
+        return Err(K8sError::Validation(format!(
+            "invalid label key '{}': name must be 1-63 alphanumeric characters, '-', '_' or '.', \
+             and must start and end with an alphanumeric character",
+            key
+        )));
+    }
+    Ok(())
+}
+
+/// 标签的值: 为空, 或不超过 63 个字符且以字母数字开头和结尾
+pub fn validate_label_value(value: &str) -> Result<(), K8sError> {
+    if !value.is_empty() && !is_qualified_name(value) {
+        return Err(K8sError::Validation(format!(
+            "invalid label value '{}': must be 0-63 alphanumeric characters, '-', '_' or '.', \
+             and must start and end with an alphanumeric character",
+            value
+        )));
+    }
+    Ok(())
+}
+
+fn is_qualified_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    name.len() <= 63
+        && bytes.first().is_some_and(u8::is_ascii_alphanumeric)
+        && bytes.last().is_some_and(u8::is_ascii_alphanumeric)
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+fn is_dns_subdomain(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            let bytes = label.as_bytes();
+            label.len() <= 63
+                && bytes
+                    .first()
+                    .is_some_and(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+                && bytes
+                    .last()
+                    .is_some_and(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+                && bytes
+                    .iter()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_selector_test() {
+        let selector = LabelSelector::new()
+            .eq("tier", "control-plane")
+            .ne("env", "prod")
+            .in_values("app", &["web", "api"])
+            .not_in("app.kubernetes.io/name", &["db"])
+            .exists("release")
+            .not_exists("canary");
+        assert_eq!(
+            selector.to_string(),
+            "tier=control-plane,env!=prod,app in (web,api),app.kubernetes.io/name notin (db),release,!canary"
+        );
+        assert!(selector.validate().is_ok());
+    }
+
+    #[test]
+    fn query_encode_test() {
+        let params = ListParams::new()
+            .labels(LabelSelector::new().in_values("app", &["web", "api"]))
+            .fields(FieldSelector::new().eq("status.phase", "Running"))
+            .timeout(30)
+            .resource_version("0");
+        assert_eq!(
+            params.query().unwrap(),
+            "labelSelector=app%20in%20%28web%2Capi%29&fieldSelector=status.phase%3DRunning&timeoutSeconds=30&resourceVersion=0"
+        );
+        assert_eq!(ListParams::new().query().unwrap(), "");
+    }
+
+    #[test]
+    fn validate_test() {
+        assert!(validate_label_key("app").is_ok());
+        assert!(validate_label_key("app.kubernetes.io/name").is_ok());
+        assert!(validate_label_key("-app").is_err());
+        assert!(validate_label_key("Example.com/app").is_err());
+        assert!(validate_label_key("example.com/").is_err());
+        assert!(validate_label_key(&"a".repeat(64)).is_err());
+        assert!(validate_label_value("").is_ok());
+        assert!(validate_label_value("v1.0_beta").is_ok());
+        assert!(validate_label_value("a b").is_err());
+        assert!(LabelSelector::new()
+            .in_values("app", &[])
+            .validate()
+            .is_err());
+        assert!(ListParams::new()
+            .labels(LabelSelector::new().eq("app", "a=b"))
+            .query()
+            .is_err());
+    }
+}
@@ -0,0 +1,225 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use k8s_openapi::Resource;
+use kube::core::DynamicObject;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::discovery::DiscoveredResource;
+use crate::k8s::error::K8sError;
+use crate::k8s::params::encode;
+use crate::k8s::resource::resource_path;
+
+/// 修改资源的方式, 参考 [Update API Objects in Place](https://kubernetes.io/docs/tasks/manage-kubernetes-objects/update-api-object-kubectl-patch/)
+#[derive(Clone, Debug)]
+pub enum Patch<T: Serialize> {
+    /// [JSON Patch](https://tools.ietf.org/html/rfc6902), 操作数组, 例如 `[{"op": "replace", "path": "/spec/replicas", "value": 3}]`
+    Json(serde_json::Value),
+
+    /// [JSON Merge Patch](https://tools.ietf.org/html/rfc7386), 列表会被整体替换
+    Merge(T),
+
+    /// Strategic Merge Patch, 按照字段的 patchStrategy 合并列表, 只支持内置资源
+    Strategic(T),
+
+    /// Server-Side Apply, 需要完整的对象 (包含 `apiVersion` 和 `kind`) 以及 `fieldManager`
+    Apply(T),
+}
+
+impl<T: Serialize> Patch<T> {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Patch::Json(_) => "application/json-patch+json",
+            Patch::Merge(_) => "application/merge-patch+json",
+            Patch::Strategic(_) => "application/strategic-merge-patch+json",
+            Patch::Apply(_) => "application/apply-patch+yaml",
+        }
+    }
+
+    /// 请求体, JSON 同时也是合法的 YAML, Server-Side Apply 也使用 JSON 发送
+    fn body(&self) -> Result<Vec<u8>, K8sError> {
+        Ok(match self {
+            Patch::Json(operations) => serde_json::to_vec(operations)?,
+            Patch::Merge(patch) | Patch::Strategic(patch) | Patch::Apply(patch) => {
+                serde_json::to_vec(patch)?
+            }
+        })
+    }
+}
+
+/// patch 请求的参数
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PatchParams {
+    /// 修改者的名称, Server-Side Apply 必须设置
+    pub field_manager: Option<String>,
+
+    /// Server-Side Apply 时强制获取冲突字段的所有权
+    pub force: bool,
+
+    /// 只在服务端校验, 不持久化
+    pub dry_run: bool,
+}
+
+impl PatchParams {
+    /// Server-Side Apply 的参数
+    pub fn apply(field_manager: &str) -> Self {
+        PatchParams {
+            field_manager: Some(field_manager.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// 与其它 field manager 冲突时强制覆盖, 相当于 `kubectl apply --server-side --force-conflicts`
+    pub fn force(mut self) -> Self {
+        self.force = true;
+        self
+    }
+
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    fn validate<T: Serialize>(&self, patch: &Patch<T>) -> Result<(), K8sError> {
+        let is_apply = matches!(patch, Patch::Apply(_));
+        if is_apply && self.field_manager.is_none() {
+            return Err(K8sError::Validation(
+                "server-side apply requires a field manager".to_string(),
+            ));
+        }
+        if !is_apply && self.force {
+            return Err(K8sError::Validation(
+                "force is only supported by server-side apply".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn query(&self) -> String {
+        let mut args = vec![];
+        if let Some(field_manager) = &self.field_manager {
+            args.push(format!("fieldManager={}", encode(field_manager)));
+        }
+        if self.force {
+            args.push("force=true".to_string());
+        }
+        if self.dry_run {
+            args.push("dryRun=All".to_string());
+        }
+        args.join("&")
+    }
+}
+
+impl HttpClient {
+    /// 修改资源, `namespace` 为 `None` 时使用 context 的默认命名空间.
+    ///
+    /// Server-Side Apply 与其它 field manager 冲突时返回 409 [`K8sError::Api`],
+    /// 冲突的字段在 `causes` 中, 可以使用 [`PatchParams::force`] 强制覆盖.
+    pub fn patch<K, P>(
+        &self,
+        namespace: Option<&str>,
+        name: &str,
+        params: &PatchParams,
+        patch: &Patch<P>,
+    ) -> Result<K, K8sError>
+    where
+        K: Resource + DeserializeOwned,
+        K::Scope: 'static,
+        P: Serialize,
+    {
+        let namespace = namespace.unwrap_or(self.default_namespace());
+        let path = resource_path::<K>(Some(namespace), Some(name));
+        self.patch_path(&path, params, patch)
+    }
+
+    /// 修改动态资源, 通常配合 [`HttpClient::resolve`] 使用
+    pub fn patch_dynamic<P: Serialize>(
+        &self,
+        resource: &DiscoveredResource,
+        namespace: Option<&str>,
+        name: &str,
+        params: &PatchParams,
+        patch: &Patch<P>,
+    ) -> Result<DynamicObject, K8sError> {
+        let namespace = namespace.unwrap_or(self.default_namespace());
+        let path = resource.path(Some(namespace), Some(name));
+        self.patch_path(&path, params, patch)
+    }
+
+    fn patch_path<T, P>(
+        &self,
+        path: &str,
+        params: &PatchParams,
+        patch: &Patch<P>,
+    ) -> Result<T, K8sError>
+    where
+        T: DeserializeOwned,
+        P: Serialize,
+    {
+        params.validate(patch)?;
+        let url = self.url(&[path], &[&params.query()]);
+        let request = self
+            .request(Method::PATCH, &url)?
+            .header(CONTENT_TYPE, patch.content_type())
+            .body(patch.body()?);
+        Ok(self.send(request)?.json()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn content_type_test() {
+        let patch: Patch<()> = Patch::Json(json!([{"op": "add", "path": "/a", "value": 1}]));
+        assert_eq!(patch.content_type(), "application/json-patch+json");
+        assert_eq!(
+            Patch::Strategic(json!({})).content_type(),
+            "application/strategic-merge-patch+json"
+        );
+        assert_eq!(
+            Patch::Apply(json!({})).content_type(),
+            "application/apply-patch+yaml"
+        );
+    }
+
+    #[test]
+    fn params_test() {
+        let params = PatchParams::apply("rust notes").force();
+        assert_eq!(params.query(), "fieldManager=rust%20notes&force=true");
+        assert!(params.validate(&Patch::Apply(json!({}))).is_ok());
+        assert!(params.validate(&Patch::Merge(json!({}))).is_err());
+        assert!(PatchParams::default()
+            .validate(&Patch::Apply(json!({})))
+            .is_err());
+        assert_eq!(PatchParams::default().dry_run().query(), "dryRun=All");
+    }
+}
@@ -0,0 +1,393 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use k8s_openapi::api::core::v1::{Node, Pod};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use prettytable::{row, Table};
+use serde::{Deserialize, Serialize};
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::error::K8sError;
+
+/// 表格中按顺序展示的 Pod 阶段
+const POD_PHASES: &[&str] = &["Running", "Pending", "Succeeded", "Failed", "Unknown"];
+
+/// 集群资产报告: 节点, 各命名空间的 Pod 数量, 有不可用副本的工作负载
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClusterReport {
+    pub nodes: Vec<NodeReport>,
+    pub namespaces: Vec<NamespacePods>,
+    pub unavailable_workloads: Vec<WorkloadReport>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NodeReport {
+    pub name: String,
+    pub kubelet_version: String,
+    pub ready: bool,
+    pub capacity: BTreeMap<String, String>,
+    pub allocatable: BTreeMap<String, String>,
+    /// 节点状况, 例如 `Ready=True`, `MemoryPressure=False`
+    pub conditions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NamespacePods {
+    pub namespace: String,
+    /// 各阶段的 Pod 数量
+    pub phases: BTreeMap<String, usize>,
+    pub total: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkloadReport {
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+    pub desired: i32,
+    pub available: i32,
+    pub unavailable: i32,
+}
+
+/// 报告的输出格式
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ReportFormat {
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
+impl FromStr for ReportFormat {
+    type Err = K8sError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(ReportFormat::Table),
+            "json" => Ok(ReportFormat::Json),
+            "yaml" => Ok(ReportFormat::Yaml),
+            _ => Err(K8sError::Validation(format!(
+                "unknown report format '{}', expected table, json or yaml",
+                s
+            ))),
+        }
+    }
+}
+
+impl ClusterReport {
+    /// 按指定格式输出
+    pub fn render(&self, format: ReportFormat) -> Result<String, K8sError> {
+        match format {
+            ReportFormat::Table => Ok(self.to_string()),
+            ReportFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+            ReportFormat::Yaml => {
+                serde_yaml::to_string(self).map_err(|e| K8sError::Decode(e.to_string()))
+            }
+        }
+    }
+
+    fn node_table(&self) -> Table {
+        let mut table = Table::new();
+        table.set_titles(row![
+            "NODE",
+            "STATUS",
+            "VERSION",
+            "CPU",
+            "MEMORY",
+            "PODS",
+            "CONDITIONS"
+        ]);
+        for node in &self.nodes {
+            let resource = |name: &str| {
+                format!(
+                    "{}/{}",
+                    node.allocatable
+                        .get(name)
+                        .map(String::as_str)
+                        .unwrap_or("-"),
+                    node.capacity.get(name).map(String::as_str).unwrap_or("-")
+                )
+            };
+            // 只展示异常的状况
+            let problems: Vec<&str> = node
+                .conditions
+                .iter()
+                .map(String::as_str)
+                .filter(|c| !matches!(*c, "Ready=True") && !c.ends_with("=False"))
+                .collect();
+            table.add_row(row![
+                node.name,
+                if node.ready { "Ready" } else { "NotReady" },
+                node.kubelet_version,
+                resource("cpu"),
+                resource("memory"),
+                resource("pods"),
+                problems.join(",")
+            ]);
+        }
+        table
+    }
+
+    fn namespace_table(&self) -> Table {
+        let mut table = Table::new();
+        let mut titles = vec!["NAMESPACE".to_string()];
+        titles.extend(POD_PHASES.iter().map(|phase| phase.to_uppercase()));
+        titles.push("TOTAL".to_string());
+        table.set_titles(titles.into());
+        for namespace in &self.namespaces {
+            let mut cells = vec![namespace.namespace.clone()];
+            cells.extend(POD_PHASES.iter().map(|phase| {
+                namespace
+                    .phases
+                    .get(*phase)
+                    .copied()
+                    .unwrap_or_default()
+                    .to_string()
+            }));
+            cells.push(namespace.total.to_string());
+            table.add_row(cells.into());
+        }
+        table
+    }
+
+    fn workload_table(&self) -> Table {
+        let mut table = Table::new();
+        table.set_titles(row![
+            "KIND",
+            "NAMESPACE",
+            "NAME",
+            "DESIRED",
+            "AVAILABLE",
+            "UNAVAILABLE"
+        ]);
+        for workload in &self.unavailable_workloads {
+            table.add_row(row![
+                workload.kind,
+                workload.namespace,
+                workload.name,
+                workload.desired,
+                workload.available,
+                workload.unavailable
+            ]);
+        }
+        table
+    }
+}
+
+impl fmt::Display for ClusterReport {
+    /// 以 `prettytable` 表格输出
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Nodes:\n{}", self.node_table())?;
+        writeln!(f, "Pods:\n{}", self.namespace_table())?;
+        if self.unavailable_workloads.is_empty() {
+            writeln!(f, "Unavailable workloads: none")
+        } else {
+            writeln!(f, "Unavailable workloads:\n{}", self.workload_table())
+        }
+    }
+}
+
+impl HttpClient {
+    /// 收集所有命名空间的集群资产报告
+    pub fn cluster_report(&self) -> Result<ClusterReport, K8sError> {
+        let nodes = self
+            .list_all::<Node>(None, &[])
+            .map(|node| node.map(node_report))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut namespaces: BTreeMap<String, NamespacePods> = BTreeMap::new();
+        for pod in self.list_all::<Pod>(None, &[]) {
+            let pod = pod?;
+            let namespace = pod.metadata.namespace.unwrap_or_default();
+            let phase = pod
+                .status
+                .and_then(|status| status.phase)
+                .unwrap_or_else(|| "Unknown".to_string());
+            let entry = namespaces
+                .entry(namespace.clone())
+                .or_insert_with(|| NamespacePods {
+                    namespace,
+                    ..Default::default()
+                });
+            *entry.phases.entry(phase).or_default() += 1;
+            entry.total += 1;
+        }
+
+        let mut workloads = vec![];
+        for deployment in self.list_all::<Deployment>(None, &[]) {
+            let deployment = deployment?;
+            let desired = deployment.spec.and_then(|spec| spec.replicas).unwrap_or(1);
+            let available = deployment
+                .status
+                .and_then(|status| status.available_replicas)
+                .unwrap_or_default();
+            workloads.push(workload(
+                "Deployment",
+                deployment.metadata,
+                desired,
+                available,
+            ));
+        }
+        for stateful_set in self.list_all::<StatefulSet>(None, &[]) {
+            let stateful_set = stateful_set?;
+            let desired = stateful_set
+                .spec
+                .and_then(|spec| spec.replicas)
+                .unwrap_or(1);
+            let available = stateful_set
+                .status
+                .and_then(|status| status.available_replicas)
+                .unwrap_or_default();
+            workloads.push(workload(
+                "StatefulSet",
+                stateful_set.metadata,
+                desired,
+                available,
+            ));
+        }
+        for daemon_set in self.list_all::<DaemonSet>(None, &[]) {
+            let daemon_set = daemon_set?;
+            let status = daemon_set.status.unwrap_or_default();
+            workloads.push(workload(
+                "DaemonSet",
+                daemon_set.metadata,
+                status.desired_number_scheduled,
+                status.number_available.unwrap_or_default(),
+            ));
+        }
+
+        Ok(ClusterReport {
+            nodes,
+            namespaces: namespaces.into_values().collect(),
+            unavailable_workloads: workloads
+                .into_iter()
+                .filter(|workload| workload.unavailable > 0)
+                .collect(),
+        })
+    }
+}
+
+fn node_report(node: Node) -> NodeReport {
+    let status = node.status.unwrap_or_default();
+    let quantities = |map: Option<BTreeMap<String, Quantity>>| {
+        map.unwrap_or_default()
+            .into_iter()
+            .map(|(name, quantity)| (name, quantity.0))
+            .collect()
+    };
+    let conditions = status.conditions.unwrap_or_default();
+    NodeReport {
+        name: node.metadata.name.unwrap_or_default(),
+        kubelet_version: status
+            .node_info
+            .map(|info| info.kubelet_version)
+            .unwrap_or_default(),
+        ready: conditions
+            .iter()
+            .any(|c| c.type_ == "Ready" && c.status == "True"),
+        capacity: quantities(status.capacity),
+        allocatable: quantities(status.allocatable),
+        conditions: conditions
+            .iter()
+            .map(|c| format!("{}={}", c.type_, c.status))
+            .collect(),
+    }
+}
+
+fn workload(kind: &str, metadata: ObjectMeta, desired: i32, available: i32) -> WorkloadReport {
+    WorkloadReport {
+        kind: kind.to_string(),
+        namespace: metadata.namespace.unwrap_or_default(),
+        name: metadata.name.unwrap_or_default(),
+        desired,
+        available,
+        unavailable: (desired - available).max(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> ClusterReport {
+        ClusterReport {
+            nodes: vec![NodeReport {
+                name: "node-1".to_string(),
+                kubelet_version: "v1.28.0".to_string(),
+                ready: false,
+                capacity: BTreeMap::from([("cpu".to_string(), "4".to_string())]),
+                allocatable: BTreeMap::from([("cpu".to_string(), "3800m".to_string())]),
+                conditions: vec!["Ready=False".to_string(), "DiskPressure=True".to_string()],
+            }],
+            namespaces: vec![NamespacePods {
+                namespace: "default".to_string(),
+                phases: BTreeMap::from([("Running".to_string(), 2)]),
+                total: 2,
+            }],
+            unavailable_workloads: vec![],
+        }
+    }
+
+    #[test]
+    fn table_test() {
+        let table = report().render(ReportFormat::Table).unwrap();
+        assert!(table.contains("NotReady"));
+        assert!(table.contains("3800m/4"));
+        assert!(table.contains("DiskPressure=True"));
+        assert!(!table.contains("Ready=False"));
+        assert!(table.contains("Unavailable workloads: none"));
+    }
+
+    #[test]
+    fn format_test() {
+        assert_eq!("YAML".parse::<ReportFormat>().unwrap(), ReportFormat::Yaml);
+        assert!("xml".parse::<ReportFormat>().is_err());
+        let json = report().render(ReportFormat::Json).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ClusterReport>(&json).unwrap(),
+            report()
+        );
+        let yaml = report().render(ReportFormat::Yaml).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<ClusterReport>(&yaml).unwrap(),
+            report()
+        );
+    }
+
+    #[test]
+    fn workload_test() {
+        let metadata = ObjectMeta {
+            name: Some("web".to_string()),
+            ..Default::default()
+        };
+        let workload = workload("Deployment", metadata, 3, 1);
+        assert_eq!(workload.unavailable, 2);
+    }
+}
@@ -0,0 +1,127 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::any::TypeId;
+
+use k8s_openapi::{NamespaceResourceScope, Resource};
+
+/// 资源所属 group/version 的 API 路径, core 组为 `/api/v1`, 其它组为 `/apis/{group}/{version}`
+pub fn api_path<K: Resource>() -> String {
+    group_version_path(K::GROUP, K::VERSION)
+}
+
+/// group/version 的 API 路径, `group` 为空时表示 core 组
+pub fn group_version_path(group: &str, version: &str) -> String {
+    if group.is_empty() {
+        format!("/api/{}", version)
+    } else {
+        format!("/apis/{}/{}", group, version)
+    }
+}
+
+/// 资源是否属于命名空间, 例如 Pod 属于命名空间, Node 不属于
+pub fn is_namespaced<K: Resource>() -> bool
+where
+    K::Scope: 'static,
+{
+    TypeId::of::<K::Scope>() == TypeId::of::<NamespaceResourceScope>()
+}
+
+/// 计算资源的 URL 路径.
+///
+/// 命名空间资源在 `namespace` 为 `None` 时表示所有命名空间;
+/// 集群资源忽略 `namespace` 参数.
+pub fn resource_path<K: Resource>(namespace: Option<&str>, name: Option<&str>) -> String
+where
+    K::Scope: 'static,
+{
+    object_path(
+        &api_path::<K>(),
+        K::URL_PATH_SEGMENT,
+        is_namespaced::<K>(),
+        namespace,
+        name,
+    )
+}
+
+/// 根据 group/version 路径和资源复数名称计算 URL 路径, 规则与 [`resource_path`] 相同
+pub fn object_path(
+    api_path: &str,
+    plural: &str,
+    namespaced: bool,
+    namespace: Option<&str>,
+    name: Option<&str>,
+) -> String {
+    let mut path = api_path.to_string();
+    if let Some(namespace) = namespace.filter(|_| namespaced) {
+        path.push_str("/namespaces/");
+        path.push_str(namespace);
+    }
+    path.push('/');
+    path.push_str(plural);
+    if let Some(name) = name {
+        path.push('/');
+        path.push_str(name);
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::apps::v1::Deployment;
+    use k8s_openapi::api::core::v1::{ConfigMap, Namespace, Node, Pod};
+
+    use super::*;
+
+    #[test]
+    fn api_path_test() {
+        assert_eq!(api_path::<Pod>(), "/api/v1");
+        assert_eq!(api_path::<Deployment>(), "/apis/apps/v1");
+    }
+
+    #[test]
+    fn resource_path_test() {
+        assert_eq!(
+            resource_path::<Pod>(Some("kube-system"), None),
+            "/api/v1/namespaces/kube-system/pods"
+        );
+        assert_eq!(resource_path::<Pod>(None, None), "/api/v1/pods");
+        assert_eq!(
+            resource_path::<Deployment>(Some("default"), Some("nginx")),
+            "/apis/apps/v1/namespaces/default/deployments/nginx"
+        );
+        assert_eq!(
+            resource_path::<ConfigMap>(Some("default"), Some("app")),
+            "/api/v1/namespaces/default/configmaps/app"
+        );
+        assert_eq!(
+            resource_path::<Node>(Some("default"), Some("node-1")),
+            "/api/v1/nodes/node-1"
+        );
+        assert_eq!(
+            resource_path::<Namespace>(None, Some("kube-system")),
+            "/api/v1/namespaces/kube-system"
+        );
+    }
+}
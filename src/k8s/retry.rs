@@ -0,0 +1,433 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Method, StatusCode};
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::async_api::AsyncHttpClient;
+use crate::k8s::error::K8sError;
+
+/// 令牌桶限流器, 每秒生成 `qps` 个令牌, 最多积累 `burst` 个.
+///
+/// 令牌不足时预支令牌并等待, 并发的调用方按调用顺序依次获得令牌.
+#[derive(Debug)]
+pub struct RateLimiter {
+    qps: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(qps: f32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        RateLimiter {
+            qps: f64::from(qps),
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// 获取一个令牌, 必要时阻塞等待
+    pub fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// 预支一个令牌, 返回需要等待的时间
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.qps).min(self.burst);
+        bucket.last = now;
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.qps)
+        }
+    }
+}
+
+/// 请求失败后的重试策略.
+///
+/// 响应为 429, 500, 502, 503, 504 或连接被重置时, 按指数退避加随机抖动重试,
+/// 响应中有 `Retry-After` 时按其完整等待, 超过 `max_retry_after` 时不再重试, 直接返回该响应.
+/// 默认只重试幂等的请求 (GET, HEAD, PUT, DELETE, OPTIONS).
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// 最大重试次数, 0 表示不重试
+    pub max_retries: u32,
+    /// 第一次重试前的退避时间, 之后每次翻倍
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// 愿意等待的最长 `Retry-After`, 为 `None` 时不限制
+    pub max_retry_after: Option<Duration>,
+    /// 是否重试 POST, PATCH 等非幂等请求
+    pub non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            max_retry_after: None,
+            non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 不重试
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// `Retry-After` 超过 `max_retry_after` 时放弃重试, 返回服务端的响应
+    pub fn max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = Some(max_retry_after);
+        self
+    }
+
+    /// 同时重试非幂等请求, 调用方需要确认重复执行是安全的
+    pub fn non_idempotent(mut self) -> Self {
+        self.non_idempotent = true;
+        self
+    }
+
+    fn allows(&self, method: &Method) -> bool {
+        self.non_idempotent
+            || matches!(
+                *method,
+                Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+            )
+    }
+
+    /// 第 `attempt` 次重试 (从 0 开始) 前的等待时间, 在退避时间的 [1/2, 1) 之间随机
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        let half = backoff / 2;
+        let jitter = RandomState::new().build_hasher().finish() % (half.as_millis() as u64 + 1);
+        half + Duration::from_millis(jitter)
+    }
+
+    /// 响应可重试时的等待时间, 优先使用 `Retry-After`. 超过 `max_retry_after` 时返回 `None`, 不再重试
+    fn response_delay(&self, headers: &HeaderMap, attempt: u32) -> Option<Duration> {
+        match retry_after(headers) {
+            Some(delay) if self.max_retry_after.is_some_and(|max| delay > max) => None,
+            Some(delay) => Some(delay),
+            None => Some(self.delay(attempt)),
+        }
+    }
+
+    /// 第 `attempt` 次请求的结果需要重试时, 返回重试前的等待时间
+    fn retry_delay(&self, attempt: u32, outcome: Outcome) -> Option<Duration> {
+        match outcome {
+            Ok((status, headers)) if retryable_status(status) => {
+                self.response_delay(headers, attempt)
+            }
+            Err(e) if is_transient(e) => Some(self.delay(attempt)),
+            _ => None,
+        }
+    }
+}
+
+/// 一次请求的结果: 响应的状态码和响应头, 或请求错误. 同步和异步客户端共用
+type Outcome<'a> = Result<(StatusCode, &'a HeaderMap), &'a reqwest::Error>;
+
+fn log_retry(
+    method: &Method,
+    url: &str,
+    delay: Duration,
+    attempt: u32,
+    max_retries: u32,
+    outcome: Outcome,
+) {
+    log::debug!(
+        "retry {} {} in {:?} ({}/{}): {}",
+        method,
+        url,
+        delay,
+        attempt + 1,
+        max_retries,
+        match outcome {
+            Ok((status, _)) => status.to_string(),
+            Err(e) => e.to_string(),
+        }
+    );
+}
+
+fn retryable_status(code: StatusCode) -> bool {
+    matches!(code.as_u16(), 429 | 500 | 502 | 503 | 504)
+}
+
+/// 解析秒数或 HTTP 日期格式的 `Retry-After`
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+        .or(Some(Duration::ZERO))
+}
+
+/// 连接失败或连接被对端重置
+fn is_transient(error: &reqwest::Error) -> bool {
+    if error.is_connect() {
+        return true;
+    }
+    let mut source = std::error::Error::source(error);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            if matches!(
+                io.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::UnexpectedEof
+            ) {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+impl HttpClient {
+    /// 客户端限流, 每秒最多 `qps` 个请求, 允许 `burst` 个突发请求. `qps` 不大于 0 时不限流
+    pub fn rate_limit(mut self, qps: f32, burst: u32) -> Self {
+        self.rate_limiter = (qps > 0.0).then(|| RateLimiter::new(qps, burst));
+        self
+    }
+
+    /// 请求失败后的重试策略, 默认为 [`RetryPolicy::default`]
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// 经过限流和重试发送请求, 返回最后一次的响应, 不检查状态码
+    pub fn execute(&self, request: RequestBuilder) -> Result<Response, K8sError> {
+        let mut request = request.build()?;
+        let mut attempt = 0;
+        loop {
+            // 请求体无法复制时不重试
+            let next = (attempt < self.retry_policy.max_retries
+                && self.retry_policy.allows(request.method()))
+            .then(|| request.try_clone())
+            .flatten();
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.acquire();
+            }
+            let method = request.method().clone();
+            let url = request.url().to_string();
+            let result = self.client.execute(request);
+            let Some(next) = next else {
+                return Ok(result?);
+            };
+            let outcome = result.as_ref().map(|r| (r.status(), r.headers()));
+            let Some(delay) = self.retry_policy.retry_delay(attempt, outcome) else {
+                return Ok(result?);
+            };
+            let max_retries = self.retry_policy.max_retries;
+            log_retry(&method, &url, delay, attempt, max_retries, outcome);
+            std::thread::sleep(delay);
+            request = next;
+            attempt += 1;
+        }
+    }
+}
+
+impl AsyncHttpClient {
+    /// 客户端限流, 与 [`HttpClient::rate_limit`] 相同
+    pub fn rate_limit(mut self, qps: f32, burst: u32) -> Self {
+        self.rate_limiter = (qps > 0.0).then(|| RateLimiter::new(qps, burst));
+        self
+    }
+
+    /// 请求失败后的重试策略, 默认为 [`RetryPolicy::default`]
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// 经过限流和重试发送请求, 返回最后一次的响应, 不检查状态码. 等待时不阻塞运行时
+    pub async fn execute(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, K8sError> {
+        let mut request = request.build()?;
+        let mut attempt = 0;
+        loop {
+            let next = (attempt < self.retry_policy.max_retries
+                && self.retry_policy.allows(request.method()))
+            .then(|| request.try_clone())
+            .flatten();
+            if let Some(rate_limiter) = &self.rate_limiter {
+                let wait = rate_limiter.reserve(Instant::now());
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+            }
+            let method = request.method().clone();
+            let url = request.url().to_string();
+            let result = self.client.execute(request).await;
+            let Some(next) = next else {
+                return Ok(result?);
+            };
+            let outcome = result.as_ref().map(|r| (r.status(), r.headers()));
+            let Some(delay) = self.retry_policy.retry_delay(attempt, outcome) else {
+                return Ok(result?);
+            };
+            let max_retries = self.retry_policy.max_retries;
+            log_retry(&method, &url, delay, attempt, max_retries, outcome);
+            tokio::time::sleep(delay).await;
+            request = next;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter_test() {
+        let limiter = RateLimiter::new(2.0, 2);
+        let start = limiter.bucket.lock().unwrap().last;
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
+        // 令牌用完后按 qps 排队
+        assert_eq!(limiter.reserve(start), Duration::from_millis(500));
+        assert_eq!(limiter.reserve(start), Duration::from_millis(1000));
+        // 空闲足够久后恢复到 burst
+        let later = start + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert!(limiter.reserve(later) > Duration::ZERO);
+    }
+
+    #[test]
+    fn retry_policy_test() {
+        let policy = RetryPolicy::default();
+        assert!(policy.allows(&Method::GET));
+        assert!(policy.allows(&Method::DELETE));
+        assert!(!policy.allows(&Method::POST));
+        assert!(!policy.allows(&Method::PATCH));
+        assert!(policy.clone().non_idempotent().allows(&Method::POST));
+
+        for attempt in 0..10 {
+            let delay = policy.delay(attempt);
+            let backoff = (Duration::from_millis(200) * 2u32.pow(attempt)).min(policy.max_backoff);
+            assert!(delay >= backoff / 2 && delay <= backoff, "{:?}", delay);
+        }
+        assert!(retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!retryable_status(StatusCode::NOT_FOUND));
+        assert!(!retryable_status(StatusCode::NOT_IMPLEMENTED));
+    }
+
+    #[test]
+    fn retry_after_test() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, "3".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+        headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+
+        // Retry-After 不受 max_backoff 限制
+        let policy = RetryPolicy::default();
+        headers.insert(RETRY_AFTER, "86400".parse().unwrap());
+        assert_eq!(
+            policy.response_delay(&headers, 0),
+            Some(Duration::from_secs(86400))
+        );
+        headers.insert(RETRY_AFTER, "1".parse().unwrap());
+        assert_eq!(
+            policy.response_delay(&headers, 0),
+            Some(Duration::from_secs(1))
+        );
+        headers.remove(RETRY_AFTER);
+        assert!(policy.response_delay(&headers, 0).unwrap() <= policy.initial_backoff);
+
+        // 超过 max_retry_after 时不重试
+        let policy = policy.max_retry_after(Duration::from_secs(60));
+        headers.insert(RETRY_AFTER, "86400".parse().unwrap());
+        assert_eq!(policy.response_delay(&headers, 0), None);
+        let outcome = Ok((StatusCode::TOO_MANY_REQUESTS, &headers));
+        assert_eq!(policy.retry_delay(0, outcome), None);
+        headers.insert(RETRY_AFTER, "60".parse().unwrap());
+        assert_eq!(
+            policy.response_delay(&headers, 0),
+            Some(Duration::from_secs(60))
+        );
+    }
+}
@@ -0,0 +1,248 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use base64::Engine;
+use k8s_openapi::api::authentication::v1::{TokenRequest, TokenRequestSpec};
+use k8s_openapi::api::core::v1::{ConfigMap, ServiceAccount};
+use k8s_openapi::api::rbac::v1::{PolicyRule, Role, RoleBinding, RoleRef, Subject};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::config::{AuthInfo, Cluster, Context};
+use reqwest::Method;
+use secrecy::SecretString;
+
+use crate::k8s::api::HttpClient;
+use crate::k8s::error::K8sError;
+use crate::k8s::kubeconfig::KubeconfigEditor;
+use crate::k8s::patch::{Patch, PatchParams};
+use crate::k8s::resource::resource_path;
+
+/// 创建 ServiceAccount, Role 和 RoleBinding 时使用的 field manager
+const FIELD_MANAGER: &str = "rust-notes-service-account";
+
+/// 每个命名空间中都有的 CA 证书 ConfigMap, 由 kube-controller-manager 维护
+const ROOT_CA_CONFIG_MAP: &str = "kube-root-ca.crt";
+
+/// 为 ServiceAccount 生成 kubeconfig 的参数.
+///
+/// ```
+/// use rust_notes::k8s::service_account::ServiceAccountKubeconfig;
+///
+/// let request = ServiceAccountKubeconfig::new("ci", "deployer")
+///     .rule(&["apps"], &["deployments"], &["get", "list", "patch"])
+///     .expiration_seconds(3600);
+/// assert_eq!(request.rules.len(), 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ServiceAccountKubeconfig {
+    pub namespace: String,
+    pub name: String,
+    /// 为空时不创建 Role 和 RoleBinding
+    pub rules: Vec<PolicyRule>,
+    /// token 有效期, 为 `None` 时使用 apiserver 的默认值 (通常为 1 小时)
+    pub expiration_seconds: Option<i64>,
+    pub audiences: Vec<String>,
+    /// kubeconfig 中的 cluster 名称
+    pub cluster_name: String,
+    /// 与其它 field manager 冲突时强制覆盖已有的 ServiceAccount, Role 和 RoleBinding, 默认返回冲突错误
+    pub force: bool,
+}
+
+impl ServiceAccountKubeconfig {
+    pub fn new(namespace: &str, name: &str) -> Self {
+        ServiceAccountKubeconfig {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            cluster_name: "kubernetes".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// 添加一条 Role 规则, core 组使用 `""`
+    pub fn rule(mut self, api_groups: &[&str], resources: &[&str], verbs: &[&str]) -> Self {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        self.rules.push(PolicyRule {
+            api_groups: Some(strings(api_groups)),
+            resources: Some(strings(resources)),
+            verbs: strings(verbs),
+            ..Default::default()
+        });
+        self
+    }
+
+    pub fn expiration_seconds(mut self, seconds: i64) -> Self {
+        self.expiration_seconds = Some(seconds);
+        self
+    }
+
+    pub fn audience(mut self, audience: &str) -> Self {
+        self.audiences.push(audience.to_string());
+        self
+    }
+
+    pub fn cluster_name(mut self, cluster_name: &str) -> Self {
+        self.cluster_name = cluster_name.to_string();
+        self
+    }
+
+    /// 强制接管其它 field manager 管理的字段
+    pub fn force(mut self) -> Self {
+        self.force = true;
+        self
+    }
+
+    fn metadata(&self) -> ObjectMeta {
+        ObjectMeta {
+            name: Some(self.name.clone()),
+            namespace: Some(self.namespace.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+impl HttpClient {
+    /// 创建 ServiceAccount, 按需创建 Role 和 RoleBinding, 通过 TokenRequest API 申请 token,
+    /// 生成内嵌集群 CA 证书的 kubeconfig. 已存在的对象通过 Server-Side Apply 更新,
+    /// 与其它 field manager 冲突时返回 409 错误, 除非设置了 [`ServiceAccountKubeconfig::force`].
+    ///
+    /// CA 证书优先使用当前配置中的 `certificate-authority-data`, 没有配置时读取命名空间中的 `kube-root-ca.crt`.
+    pub fn service_account_kubeconfig(
+        &self,
+        request: &ServiceAccountKubeconfig,
+    ) -> Result<KubeconfigEditor, K8sError> {
+        let namespace = Some(request.namespace.as_str());
+        let mut params = PatchParams::apply(FIELD_MANAGER);
+        params.force = request.force;
+        let service_account = ServiceAccount {
+            metadata: request.metadata(),
+            ..Default::default()
+        };
+        self.patch::<ServiceAccount, _>(
+            namespace,
+            &request.name,
+            &params,
+            &Patch::Apply(&service_account),
+        )?;
+
+        if !request.rules.is_empty() {
+            let role = Role {
+                metadata: request.metadata(),
+                rules: Some(request.rules.clone()),
+            };
+            self.patch::<Role, _>(namespace, &request.name, &params, &Patch::Apply(&role))?;
+            let role_binding = RoleBinding {
+                metadata: request.metadata(),
+                role_ref: RoleRef {
+                    api_group: "rbac.authorization.k8s.io".to_string(),
+                    kind: "Role".to_string(),
+                    name: request.name.clone(),
+                },
+                subjects: Some(vec![Subject {
+                    kind: "ServiceAccount".to_string(),
+                    name: request.name.clone(),
+                    namespace: Some(request.namespace.clone()),
+                    ..Default::default()
+                }]),
+            };
+            self.patch::<RoleBinding, _>(
+                namespace,
+                &request.name,
+                &params,
+                &Patch::Apply(&role_binding),
+            )?;
+        }
+
+        let token = self.service_account_token(request)?;
+        let ca = if self.tls_config.certificate_authority_data.is_empty() {
+            self.get::<ConfigMap>(namespace, ROOT_CA_CONFIG_MAP)?
+                .data
+                .and_then(|mut data| data.remove("ca.crt"))
+                .ok_or_else(|| {
+                    K8sError::Config(format!(
+                        "configmap {}/{} has no ca.crt",
+                        request.namespace, ROOT_CA_CONFIG_MAP
+                    ))
+                })?
+        } else {
+            self.tls_config.certificate_authority_data.clone()
+        };
+
+        let mut editor = KubeconfigEditor::new();
+        editor.set_cluster(
+            &request.cluster_name,
+            Cluster {
+                server: Some(self.server.clone()),
+                certificate_authority_data: Some(
+                    base64::engine::general_purpose::STANDARD.encode(ca),
+                ),
+                tls_server_name: (!self.tls_config.tls_server_name.is_empty())
+                    .then(|| self.tls_config.tls_server_name.clone()),
+                ..Default::default()
+            },
+        );
+        editor.set_user(
+            &request.name,
+            AuthInfo {
+                token: Some(SecretString::new(token)),
+                ..Default::default()
+            },
+        );
+        let context = format!("{}@{}", request.name, request.cluster_name);
+        editor.set_context(
+            &context,
+            Context {
+                cluster: request.cluster_name.clone(),
+                user: request.name.clone(),
+                namespace: Some(request.namespace.clone()),
+                ..Default::default()
+            },
+        );
+        editor.set_current_context(&context)?;
+        Ok(editor)
+    }
+
+    /// 通过 `serviceaccounts/{name}/token` 子资源申请 token
+    fn service_account_token(
+        &self,
+        request: &ServiceAccountKubeconfig,
+    ) -> Result<String, K8sError> {
+        let token_request = TokenRequest {
+            spec: TokenRequestSpec {
+                audiences: request.audiences.clone(),
+                expiration_seconds: request.expiration_seconds,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let path = resource_path::<ServiceAccount>(Some(&request.namespace), Some(&request.name));
+        let url = self.url(&[&path, "token"], &[]);
+        let response: TokenRequest = self
+            .send(self.request(Method::POST, &url)?.json(&token_request))?
+            .json()?;
+        response
+            .status
+            .map(|status| status.token)
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| K8sError::Decode("token request returned no token".into()))
+    }
+}
@@ -0,0 +1,160 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName};
+
+use crate::k8s::error::K8sError;
+use crate::k8s::models::HttpKubeConfig;
+
+/// 由 [`HttpKubeConfig`] 生成 rustls 的客户端配置, 用于 exec 的 WebSocket 连接和设置了 `tls-server-name` 的 HTTP 客户端.
+///
+/// 设置 `tls-server-name` 时按该名称校验服务端证书, 请求地址不变.
+pub(crate) fn client_config(http_config: &HttpKubeConfig) -> Result<ClientConfig, K8sError> {
+    let mut roots = RootCertStore::empty();
+    if http_config.certificate_authority_data.is_empty() {
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+    }
+    for certificate in pem_certificates(&http_config.certificate_authority_data)? {
+        roots
+            .add(&certificate)
+            .map_err(|e| K8sError::Tls(format!("invalid certificate authority: {}", e)))?;
+    }
+    let verifier = server_name(http_config)?.map(|name| ServerNameVerifier {
+        inner: WebPkiVerifier::new(roots.clone(), None),
+        name,
+    });
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+    let mut config = if http_config.client_certificate_data.is_empty()
+        || http_config.client_key_data.is_empty()
+    {
+        builder.with_no_client_auth()
+    } else {
+        let certificates = pem_certificates(&http_config.client_certificate_data)?;
+        let key = pem_private_key(&http_config.client_key_data)?;
+        builder
+            .with_client_auth_cert(certificates, key)
+            .map_err(|e| K8sError::Tls(format!("invalid client certificate: {}", e)))?
+    };
+    if http_config.insecure_skip_tls_verify {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(InsecureVerifier));
+    } else if let Some(verifier) = verifier {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(verifier));
+    }
+    Ok(config)
+}
+
+/// `tls-server-name`, 未设置时返回 `None`
+pub(crate) fn server_name(http_config: &HttpKubeConfig) -> Result<Option<ServerName>, K8sError> {
+    if http_config.tls_server_name.is_empty() {
+        return Ok(None);
+    }
+    ServerName::try_from(http_config.tls_server_name.as_str())
+        .map(Some)
+        .map_err(|e| {
+            K8sError::Config(format!(
+                "invalid tls-server-name {}: {}",
+                http_config.tls_server_name, e
+            ))
+        })
+}
+
+fn pem_certificates(pem: &str) -> Result<Vec<Certificate>, K8sError> {
+    let certificates = rustls_pemfile::certs(&mut pem.as_bytes())
+        .map_err(|e| K8sError::Tls(format!("invalid certificate: {}", e)))?;
+    Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+fn pem_private_key(pem: &str) -> Result<PrivateKey, K8sError> {
+    let items = rustls_pemfile::read_all(&mut pem.as_bytes())
+        .map_err(|e| K8sError::Tls(format!("invalid client key: {}", e)))?;
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| K8sError::Tls("no private key found in client key".to_string()))
+}
+
+/// `insecure-skip-tls-verify` 时不校验服务端证书
+struct InsecureVerifier;
+
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// 设置 `tls-server-name` 时, 使用该名称而不是请求地址中的主机名校验证书
+struct ServerNameVerifier {
+    inner: WebPkiVerifier,
+    name: ServerName,
+}
+
+impl ServerCertVerifier for ServerNameVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        _server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            &self.name,
+            scts,
+            ocsp_response,
+            now,
+        )
+    }
+}
@@ -0,0 +1,399 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, WatchEvent};
+use k8s_openapi::{ListableResource, Metadata, Resource};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+
+use crate::k8s::api::{HttpClient, LineReader, STREAM_POLL_INTERVAL};
+use crate::k8s::error::{ApiError, K8sError};
+use crate::k8s::resource::resource_path;
+
+/// 每次 watch 请求的服务端超时时间, 到期后 apiserver 会关闭连接, [`Watcher`] 会自动重新连接
+const WATCH_TIMEOUT_SECONDS: u64 = 290;
+
+/// 连续出错后重新连接前的等待时间, 每次翻倍, 最长为 [`WATCH_BACKOFF_MAX`]
+const WATCH_BACKOFF_INITIAL: Duration = Duration::from_millis(500);
+const WATCH_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// [`Watcher`] 产生的事件
+#[derive(Debug)]
+pub enum Event<K> {
+    /// 当前全部对象, 在首次 list 和收到 `410 Gone` 重新 list 后产生
+    Restarted(Vec<K>),
+    Added(K),
+    Modified(K),
+    Deleted(K),
+    /// 书签事件, 只更新 resourceVersion
+    Bookmark(String),
+}
+
+/// 解析 apiserver 按行返回的 `WatchEvent` 流的阻塞迭代器.
+///
+/// 记录最后一次收到的 `resourceVersion`, 连接超时或断开后从该版本继续 watch,
+/// 版本过期 (`410 Gone`) 时重新 list. 出错后返回 `Err`, 出错或连接没有收到任何事件就结束时,
+/// 按指数退避等待后再重新连接. 设置 [`Watcher::stop_on`] 后可以从其他线程结束 watch.
+pub struct Watcher<'a, K> {
+    client: &'a HttpClient,
+    namespace: Option<String>,
+    args: Vec<String>,
+    resource_version: Option<String>,
+    lines: Option<LineReader>,
+    /// 为 `true` 时迭代器结束
+    stopped: Option<&'a AtomicBool>,
+    /// 连续出错的次数, 收到事件后清零
+    failures: u32,
+    /// 当前连接是否收到过事件
+    received: bool,
+    _marker: PhantomData<K>,
+}
+
+impl HttpClient {
+    /// 先 list 再 watch 资源, 命名空间资源在 `namespace` 为 `None` 时 watch 所有命名空间
+    pub fn watch<K>(&self, namespace: Option<&str>, args: &[&str]) -> Watcher<'_, K> {
+        Watcher {
+            client: self,
+            namespace: namespace.map(String::from),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            resource_version: None,
+            lines: None,
+            stopped: None,
+            failures: 0,
+            received: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 从指定的 `resourceVersion` 开始 watch, 不会产生 [`Event::Restarted`] 事件, 除非版本已过期
+    pub fn watch_from<K>(
+        &self,
+        namespace: Option<&str>,
+        args: &[&str],
+        resource_version: &str,
+    ) -> Watcher<'_, K> {
+        let mut watcher = self.watch(namespace, args);
+        watcher.resource_version = Some(resource_version.to_string());
+        watcher
+    }
+}
+
+impl<'a, K> Watcher<'a, K>
+where
+    K: Resource + ListableResource + Metadata<Ty = ObjectMeta> + DeserializeOwned,
+    K::Scope: 'static,
+{
+    /// `stopped` 为 `true` 后迭代器返回 `None` 并关闭连接. 等待事件或重连退避时几秒内就会检查一次
+    pub fn stop_on(mut self, stopped: &'a AtomicBool) -> Self {
+        self.stopped = Some(stopped);
+        self
+    }
+
+    /// 最后一次收到的 `resourceVersion`
+    pub fn resource_version(&self) -> Option<&str> {
+        self.resource_version.as_deref()
+    }
+
+    fn args(&self) -> Vec<&str> {
+        self.args.iter().map(String::as_str).collect()
+    }
+
+    fn relist(&mut self) -> Result<Event<K>, K8sError> {
+        let list = self
+            .client
+            .list::<K>(self.namespace.as_deref(), &self.args())?;
+        self.resource_version = list.metadata.resource_version;
+        Ok(Event::Restarted(list.items))
+    }
+
+    /// 建立 watch 连接, 版本已过期时返回 `None`
+    fn connect(&self) -> Result<Option<LineReader>, K8sError> {
+        let resource_version = format!(
+            "resourceVersion={}",
+            self.resource_version.as_deref().unwrap_or_default()
+        );
+        let timeout = format!("timeoutSeconds={}", WATCH_TIMEOUT_SECONDS);
+        let mut args = self.args();
+        args.extend([
+            "watch=true",
+            "allowWatchBookmarks=true",
+            &resource_version,
+            &timeout,
+        ]);
+        let path = resource_path::<K>(self.namespace.as_deref(), None);
+        let url = self.client.url(&[&path], &args);
+        let request = self
+            .client
+            .request(Method::GET, &url)?
+            // 读取超时后检查是否需要停止, 然后继续等待, 不会中断连接
+            .timeout(STREAM_POLL_INTERVAL);
+        let response = self.client.execute(request)?;
+        if response.status() == StatusCode::GONE {
+            return Ok(None);
+        }
+        Ok(Some(LineReader::new(HttpClient::check(response)?)))
+    }
+
+    /// 处理一行 watch 事件, 返回 `None` 表示需要继续读取
+    fn handle(&mut self, line: &str) -> Option<Result<Event<K>, K8sError>> {
+        if line.trim().is_empty() {
+            return None;
+        }
+        let event = match serde_json::from_str::<WatchEvent<K>>(line) {
+            Ok(event) => event,
+            Err(e) => return Some(Err(K8sError::Decode(format!("parse watch event: {}", e)))),
+        };
+        let event = match event {
+            WatchEvent::Added(object) => Event::Added(self.track(object)),
+            WatchEvent::Modified(object) => Event::Modified(self.track(object)),
+            WatchEvent::Deleted(object) => Event::Deleted(self.track(object)),
+            WatchEvent::Bookmark {
+                resource_version, ..
+            } => {
+                self.resource_version = Some(resource_version.clone());
+                Event::Bookmark(resource_version)
+            }
+            WatchEvent::ErrorStatus(status) => {
+                self.lines = None;
+                if status.code == Some(410) {
+                    // resourceVersion 已过期, 下一次迭代时重新 list
+                    self.resource_version = None;
+                    return None;
+                }
+                return Some(Err(K8sError::Api(ApiError::from(status))));
+            }
+            WatchEvent::ErrorOther(raw) => {
+                self.lines = None;
+                return Some(Err(K8sError::Decode(format!("watch error: {}", raw.0))));
+            }
+        };
+        Some(Ok(event))
+    }
+
+    /// 第 `failures` 次连续出错后, 重新连接前的等待时间
+    fn backoff(&self) -> Duration {
+        if self.failures == 0 {
+            return Duration::ZERO;
+        }
+        let exponent = self.failures.saturating_sub(1).min(16);
+        WATCH_BACKOFF_INITIAL
+            .saturating_mul(1 << exponent)
+            .min(WATCH_BACKOFF_MAX)
+    }
+
+    fn track(&mut self, object: K) -> K {
+        if let Some(resource_version) = &object.metadata().resource_version {
+            self.resource_version = Some(resource_version.clone());
+        }
+        object
+    }
+}
+
+impl<'a, K> Iterator for Watcher<'a, K>
+where
+    K: Resource + ListableResource + Metadata<Ty = ObjectMeta> + DeserializeOwned,
+    K::Scope: 'static,
+{
+    type Item = Result<Event<K>, K8sError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.next_event();
+        match &item {
+            Some(Ok(_)) => {
+                self.failures = 0;
+                self.received = true;
+            }
+            Some(Err(_)) => self.failures = self.failures.saturating_add(1),
+            None => {}
+        }
+        item
+    }
+}
+
+impl<'a, K> Watcher<'a, K>
+where
+    K: Resource + ListableResource + Metadata<Ty = ObjectMeta> + DeserializeOwned,
+    K::Scope: 'static,
+{
+    fn next_event(&mut self) -> Option<Result<Event<K>, K8sError>> {
+        loop {
+            if self.lines.is_none() && self.failures > 0 {
+                self.sleep(self.backoff());
+            }
+            if self.is_stopped() {
+                self.lines = None;
+                return None;
+            }
+            if self.resource_version.is_none() {
+                return Some(self.relist());
+            }
+            if self.lines.is_none() {
+                match self.connect() {
+                    Ok(Some(lines)) => {
+                        self.lines = Some(lines);
+                        self.received = false;
+                    }
+                    Ok(None) => {
+                        self.resource_version = None;
+                        continue;
+                    }
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            let stopped = self.stopped;
+            match self
+                .lines
+                .as_mut()
+                .and_then(|lines| lines.next_line(|| is_set(stopped)))
+            {
+                Some(Ok(line)) => {
+                    if let Some(event) = self.handle(&line) {
+                        return Some(event);
+                    }
+                }
+                // 服务端超时关闭连接或读取超时, 从最后的 resourceVersion 重新连接
+                Some(Err(e)) => {
+                    log::debug!("watch connection interrupted: {}", e);
+                    self.disconnect();
+                }
+                None => self.disconnect(),
+            }
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        is_set(self.stopped)
+    }
+
+    /// 退避等待, 期间定期检查是否需要停止
+    fn sleep(&self, duration: Duration) {
+        let deadline = Instant::now() + duration;
+        while !self.is_stopped() {
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            std::thread::sleep((deadline - now).min(STREAM_POLL_INTERVAL));
+        }
+    }
+
+    /// 连接结束, 没有收到任何事件时按失败计数, 避免代理或负载均衡立即关闭连接时不断重连
+    fn disconnect(&mut self) {
+        self.lines = None;
+        if !self.received {
+            self.failures = self.failures.saturating_add(1);
+        }
+    }
+}
+
+fn is_set(flag: Option<&AtomicBool>) -> bool {
+    flag.is_some_and(|flag| flag.load(Ordering::SeqCst))
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::Pod;
+
+    use crate::k8s::models::HttpKubeConfig;
+
+    use super::*;
+
+    fn client() -> HttpClient {
+        HttpClient::new(HttpKubeConfig {
+            server: "http://127.0.0.1:6443".to_string(),
+            namespace: "default".to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn handle_events_test() {
+        let client = client();
+        let mut watcher = client.watch_from::<Pod>(Some("default"), &[], "100");
+        let added = r#"{"type":"ADDED","object":{"apiVersion":"v1","kind":"Pod","metadata":{"name":"nginx","resourceVersion":"101"}}}"#;
+        assert!(matches!(watcher.handle(added), Some(Ok(Event::Added(_)))));
+        assert_eq!(watcher.resource_version(), Some("101"));
+
+        let bookmark = r#"{"type":"BOOKMARK","object":{"apiVersion":"v1","kind":"Pod","metadata":{"resourceVersion":"120"}}}"#;
+        assert!(matches!(watcher.handle(bookmark), Some(Ok(Event::Bookmark(ref v))) if v == "120"));
+        assert_eq!(watcher.resource_version(), Some("120"));
+
+        let deleted = r#"{"type":"DELETED","object":{"apiVersion":"v1","kind":"Pod","metadata":{"name":"nginx","resourceVersion":"121"}}}"#;
+        assert!(matches!(
+            watcher.handle(deleted),
+            Some(Ok(Event::Deleted(_)))
+        ));
+        assert!(watcher.handle("").is_none());
+    }
+
+    #[test]
+    fn handle_gone_test() {
+        let client = client();
+        let mut watcher = client.watch_from::<Pod>(None, &[], "100");
+        let gone = r#"{"type":"ERROR","object":{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","message":"too old resource version: 100 (200)","reason":"Expired","code":410}}"#;
+        assert!(watcher.handle(gone).is_none());
+        assert_eq!(watcher.resource_version(), None);
+
+        let mut watcher = client.watch_from::<Pod>(None, &[], "100");
+        let forbidden = r#"{"type":"ERROR","object":{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","message":"forbidden","reason":"Forbidden","code":403}}"#;
+        assert!(matches!(watcher.handle(forbidden), Some(Err(ref e)) if e.code() == Some(403)));
+        assert_eq!(watcher.resource_version(), Some("100"));
+    }
+
+    #[test]
+    fn reconnect_backoff_test() {
+        let client = client().retry_policy(crate::k8s::retry::RetryPolicy::none());
+        let mut watcher = client.watch_from::<Pod>(None, &[], "100");
+        assert_eq!(watcher.backoff(), Duration::ZERO);
+        for expected in [500, 1000, 2000, 4000] {
+            watcher.failures += 1;
+            assert_eq!(watcher.backoff(), Duration::from_millis(expected));
+        }
+        watcher.failures = u32::MAX;
+        assert_eq!(watcher.backoff(), WATCH_BACKOFF_MAX);
+
+        // 连接失败后, 下一次迭代先等待再重新连接
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        let client = HttpClient::new(HttpKubeConfig {
+            server,
+            ..Default::default()
+        })
+        .unwrap()
+        .retry_policy(crate::k8s::retry::RetryPolicy::none());
+        let mut watcher = client.watch_from::<Pod>(None, &[], "100");
+        assert!(matches!(watcher.next(), Some(Err(_))));
+        assert_eq!(watcher.failures, 1);
+        let start = std::time::Instant::now();
+        assert!(matches!(watcher.next(), Some(Err(_))));
+        assert!(start.elapsed() >= WATCH_BACKOFF_INITIAL);
+        assert_eq!(watcher.failures, 2);
+    }
+}
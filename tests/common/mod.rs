@@ -0,0 +1,935 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2023 tomoncle
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! 进程内的模拟 apiserver, 用于在没有 k8s 集群的环境中测试 `k8s` 模块.
+//!
+//! 启动时生成 CA, 服务端证书和客户端证书, 只接受 CA 签发的客户端证书,
+//! 对 discovery, list, get, watch 和 log 请求返回固定的响应, list 支持 `limit` 和 `continue` 分页,
+//! list 和 watch 支持 `labelSelector` 和 `field=value` 形式的 `fieldSelector`, exec 请求升级为 WebSocket 连接.
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::Engine;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, SanType,
+};
+use rust_notes::k8s::models::HttpKubeConfig;
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use serde_json::{json, Value};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+/// 模拟 apiserver 的 resourceVersion
+pub const RESOURCE_VERSION: &str = "1000";
+
+pub struct MockApiServer {
+    /// `https://127.0.0.1:{port}`
+    pub server: String,
+    pub ca_pem: String,
+    pub client_cert_pem: String,
+    pub client_key_pem: String,
+    requests: Arc<Mutex<Vec<String>>>,
+    failures: Arc<Mutex<VecDeque<u16>>>,
+}
+
+impl MockApiServer {
+    /// 在随机端口上启动, 每个连接使用一个线程处理
+    pub fn start() -> MockApiServer {
+        let ca = Certificate::from_params(ca_params()).unwrap();
+        let server_cert = Certificate::from_params(server_params()).unwrap();
+        let client_cert = Certificate::from_params(client_params()).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            .with_single_cert(
+                vec![rustls::Certificate(
+                    server_cert.serialize_der_with_signer(&ca).unwrap(),
+                )],
+                rustls::PrivateKey(server_cert.serialize_private_key_der()),
+            )
+            .unwrap();
+        let config = Arc::new(config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let recorder = requests.clone();
+        let failures = Arc::new(Mutex::new(VecDeque::new()));
+        let injected = failures.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let config = config.clone();
+                let recorder = recorder.clone();
+                let injected = injected.clone();
+                std::thread::spawn(move || {
+                    // 客户端证书校验失败时握手出错, 直接关闭连接
+                    let _ = serve(config, stream, recorder, injected);
+                });
+            }
+        });
+
+        MockApiServer {
+            server: format!("https://127.0.0.1:{}", port),
+            ca_pem: ca.serialize_pem().unwrap(),
+            client_cert_pem: client_cert.serialize_pem_with_signer(&ca).unwrap(),
+            client_key_pem: client_cert.serialize_private_key_pem(),
+            requests,
+            failures,
+        }
+    }
+
+    /// 指向模拟 apiserver 的 kubeconfig, 证书以 `*-data` 的形式内嵌
+    pub fn kubeconfig(&self) -> String {
+        let encode = |pem: &str| base64::engine::general_purpose::STANDARD.encode(pem);
+        format!(
+            r###"apiVersion: v1
+clusters:
+- cluster:
+    certificate-authority-data: {}
+    server: {}
+  name: mock
+contexts:
+- context:
+    cluster: mock
+    user: mock-admin
+  name: mock-admin@mock
+current-context: mock-admin@mock
+kind: Config
+preferences: {{}}
+users:
+- name: mock-admin
+  user:
+    client-certificate-data: {}
+    client-key-data: {}
+"###,
+            encode(&self.ca_pem),
+            self.server,
+            encode(&self.client_cert_pem),
+            encode(&self.client_key_pem)
+        )
+    }
+
+    pub fn http_config(&self) -> HttpKubeConfig {
+        HttpKubeConfig::from_yaml(&self.kubeconfig()).unwrap()
+    }
+
+    /// 已收到的请求, 格式为 `METHOD /path?query`
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// 接下来的请求依次返回指定的错误状态码, 并带有 `Retry-After: 0`.
+    /// 分页查询中途注入 `410` 可以模拟 `continue` token 过期
+    pub fn fail_next(&self, codes: &[u16]) {
+        self.failures.lock().unwrap().extend(codes);
+    }
+}
+
+fn ca_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name = name("mock-kubernetes-ca", None);
+    params
+}
+
+fn server_params() -> CertificateParams {
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+    params
+        .subject_alt_names
+        .push(SanType::IpAddress(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+    params.distinguished_name = name("kube-apiserver", None);
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    params
+}
+
+fn client_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params.distinguished_name = name("mock-admin", Some("system:masters"));
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    params
+}
+
+fn name(common_name: &str, organization: Option<&str>) -> DistinguishedName {
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, common_name);
+    if let Some(organization) = organization {
+        name.push(DnType::OrganizationName, organization);
+    }
+    name
+}
+
+/// 处理一个连接上的一个请求, 响应后关闭连接
+fn serve(
+    config: Arc<ServerConfig>,
+    stream: TcpStream,
+    recorder: Arc<Mutex<Vec<String>>>,
+    failures: Arc<Mutex<VecDeque<u16>>>,
+) -> std::io::Result<()> {
+    let connection = ServerConnection::new(config).map_err(std::io::Error::other)?;
+    let mut reader = BufReader::new(StreamOwned::new(connection, stream));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    let mut headers = vec![];
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = header.split_once(':') {
+            if key.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+            headers.push((key.to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+    recorder
+        .lock()
+        .unwrap()
+        .push(format!("{} {}", method, target));
+
+    let failure = failures.lock().unwrap().pop_front();
+    let (status, content_type, body) = match failure {
+        Some(code) => status(code, "Injected", "injected failure"),
+        None => route(&method, &target, &headers, &body),
+    };
+    if status.starts_with("101") {
+        return exec(reader.into_inner(), &target, &headers);
+    }
+    let retry_after = if failure.is_some() {
+        "Retry-After: 0\r\n"
+    } else {
+        ""
+    };
+    let stream = reader.get_mut();
+    // `follow` 的日志和有事件的 watch 写完后保持连接, 直到客户端断开或 60 秒后.
+    // 没有事件的 watch 立即关闭, 模拟代理提前断开连接
+    let streaming = (target.contains("/log?") && target.contains("follow=true"))
+        || (target.contains("watch=true") && body != "\n");
+    if failure.is_none() && streaming {
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nConnection: close\r\n\r\n{}",
+            status, content_type, body
+        )?;
+        stream.flush()?;
+        stream
+            .sock
+            .set_read_timeout(Some(Duration::from_secs(60)))?;
+        let _ = stream.read(&mut [0; 1]);
+        return Ok(());
+    }
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len(),
+        retry_after
+    )?;
+    stream.write_all(body.as_bytes())?;
+    stream.conn.send_close_notify();
+    stream.flush()
+}
+
+/// `(plural, apiVersion, kind, namespaced)`
+const RESOURCES: &[(&str, &str, &str, bool)] = &[
+    ("namespaces", "v1", "Namespace", false),
+    ("nodes", "v1", "Node", false),
+    (
+        "customresourcedefinitions",
+        "apiextensions.k8s.io/v1",
+        "CustomResourceDefinition",
+        false,
+    ),
+    ("pods", "v1", "Pod", true),
+    ("configmaps", "v1", "ConfigMap", true),
+    ("serviceaccounts", "v1", "ServiceAccount", true),
+    ("services", "v1", "Service", true),
+    ("deployments", "apps/v1", "Deployment", true),
+    ("statefulsets", "apps/v1", "StatefulSet", true),
+    ("daemonsets", "apps/v1", "DaemonSet", true),
+    ("events", "events.k8s.io/v1", "Event", true),
+    ("roles", "rbac.authorization.k8s.io/v1", "Role", true),
+    (
+        "selfsubjectaccessreviews",
+        "authorization.k8s.io/v1",
+        "SelfSubjectAccessReview",
+        false,
+    ),
+    (
+        "subjectaccessreviews",
+        "authorization.k8s.io/v1",
+        "SubjectAccessReview",
+        false,
+    ),
+    (
+        "rolebindings",
+        "rbac.authorization.k8s.io/v1",
+        "RoleBinding",
+        true,
+    ),
+];
+
+/// 请求头名称为小写
+type Headers = [(String, String)];
+
+fn route(
+    method: &str,
+    target: &str,
+    headers: &Headers,
+    body: &[u8],
+) -> (&'static str, &'static str, String) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        [] => ok(
+            json!({"paths": ["/api", "/api/v1", "/apis", "/apis/apps/v1", "/healthz", "/version"]}),
+        ),
+        ["healthz"] => ("200 OK", "text/plain", "ok".to_string()),
+        ["version"] => ok(json!({"major": "1", "minor": "28", "gitVersion": "v1.28.0"})),
+        ["api"] => ok(json!({"kind": "APIVersions", "versions": ["v1"]})),
+        ["apis"] => ok(group_list()),
+        ["api", "v1"] => ok(resource_list("v1")),
+        ["apis", group, version] => ok(resource_list(&format!("{}/{}", group, version))),
+        ["api", "v1", rest @ ..] => resource(method, "v1", rest, query, headers, body),
+        ["apis", group, version, rest @ ..] => resource(
+            method,
+            &format!("{}/{}", group, version),
+            rest,
+            query,
+            headers,
+            body,
+        ),
+        _ => status(
+            404,
+            "NotFound",
+            &format!("the server could not find {}", path),
+        ),
+    }
+}
+
+fn resource(
+    method: &str,
+    api_version: &str,
+    rest: &[&str],
+    query: &str,
+    headers: &Headers,
+    body: &[u8],
+) -> (&'static str, &'static str, String) {
+    let (namespace, rest) = match rest {
+        ["namespaces", namespace, rest @ ..] if !rest.is_empty() => (Some(*namespace), rest),
+        _ => (None, rest),
+    };
+    let Some(&(plural, _, kind, _)) = RESOURCES
+        .iter()
+        .find(|r| r.1 == api_version && Some(&r.0) == rest.first())
+    else {
+        return status(
+            404,
+            "NotFound",
+            "the server could not find the requested resource",
+        );
+    };
+    match (method, &rest[1..]) {
+        ("GET", _) => {}
+        // Server-Side Apply 返回提交的对象, 内容有变化时 resourceVersion 加一
+        ("PATCH", [name]) => {
+            let mut object: Value = serde_json::from_slice(body).unwrap_or_default();
+            let existing = objects().into_iter().find(|existing| {
+                existing["kind"] == kind
+                    && existing["metadata"]["name"] == *name
+                    && (namespace.is_none()
+                        || existing["metadata"]["namespace"] == namespace.unwrap())
+            });
+            let changed = existing.as_ref().is_some_and(|existing| {
+                let fields = object.as_object().into_iter().flatten();
+                fields
+                    .filter(|(key, _)| !["apiVersion", "kind", "metadata"].contains(&key.as_str()))
+                    .any(|(key, value)| existing[key] != *value)
+                    || object["metadata"]["labels"] != existing["metadata"]["labels"]
+            });
+            let resource_version = if changed { "1001" } else { RESOURCE_VERSION };
+            object["metadata"]["resourceVersion"] = json!(resource_version);
+            return ok(object);
+        }
+        ("POST", [name, "token"]) if plural == "serviceaccounts" => {
+            let mut token_request: Value = serde_json::from_slice(body).unwrap_or_default();
+            token_request["status"] = json!({
+                "token": format!("mock-token-{}", name),
+                "expirationTimestamp": "2030-01-01T00:00:00Z"
+            });
+            return ok(token_request);
+        }
+        ("POST", []) if plural.ends_with("accessreviews") => {
+            let review: Value = serde_json::from_slice(body).unwrap_or_default();
+            return ok(access_review(review, headers));
+        }
+        _ => {
+            return status(
+                405,
+                "MethodNotAllowed",
+                "not supported by the mock apiserver",
+            )
+        }
+    }
+    let params = parse_query(query);
+    let selector = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .unwrap_or_default()
+    };
+    let (labels, fields) = (selector("labelSelector"), selector("fieldSelector"));
+    let items: Vec<Value> = objects()
+        .into_iter()
+        .filter(|object| object["kind"] == kind)
+        .filter(|object| {
+            namespace.is_none() || object["metadata"]["namespace"] == namespace.unwrap()
+        })
+        .filter(|object| matches(object, &labels) && fields_match(object, &fields))
+        .collect();
+
+    match &rest[1..] {
+        [] if params.contains(&("watch".to_string(), "true".to_string())) => {
+            let events: Vec<String> = items
+                .into_iter()
+                .map(|object| json!({"type": "ADDED", "object": object}).to_string())
+                .collect();
+            ("200 OK", "application/json", events.join("\n") + "\n")
+        }
+        [] => {
+            // `continue` 为下一页第一个对象的下标
+            let param = |key: &str| {
+                params
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .and_then(|(_, value)| value.parse::<usize>().ok())
+            };
+            let offset = param("continue").unwrap_or(0).min(items.len());
+            let limit = param("limit").unwrap_or(items.len()).max(1);
+            let end = (offset + limit).min(items.len());
+            let mut metadata = json!({"resourceVersion": RESOURCE_VERSION});
+            if end < items.len() {
+                metadata["continue"] = json!(end.to_string());
+            }
+            ok(json!({
+                "apiVersion": api_version,
+                "kind": format!("{}List", kind),
+                "metadata": metadata,
+                "items": items[offset..end]
+            }))
+        }
+        [name, sub @ ..] => {
+            let Some(object) = items
+                .into_iter()
+                .find(|object| object["metadata"]["name"] == *name)
+            else {
+                return status(
+                    404,
+                    "NotFound",
+                    &format!("{} \"{}\" not found", plural, name),
+                );
+            };
+            match sub {
+                [] => ok(object),
+                // 模拟的容器没有重启过
+                ["log"] if params.contains(&("previous".to_string(), "true".to_string())) => {
+                    status(
+                        400,
+                        "BadRequest",
+                        &format!(
+                            "previous terminated container \"main\" in pod \"{}\" not found",
+                            name
+                        ),
+                    )
+                }
+                ["log"] => (
+                    "200 OK",
+                    "text/plain",
+                    format!("{} started\n{} ready\n", name, name),
+                ),
+                ["exec"] if header(headers, "upgrade") == Some("websocket") => {
+                    ("101 Switching Protocols", "", String::new())
+                }
+                _ => status(404, "NotFound", "unknown subresource"),
+            }
+        }
+    }
+}
+
+/// 模拟的授权规则: `system:masters` 组允许所有操作, 其他用户只允许 `get`, `list` 和 `watch`.
+/// SelfSubjectAccessReview 的用户为客户端证书的用户, 或者 `Impersonate-*` 请求头中的用户
+fn access_review(mut review: Value, headers: &Headers) -> Value {
+    let (user, groups) = if review["kind"] == "SubjectAccessReview" {
+        let groups = review["spec"]["groups"]
+            .as_array()
+            .map(|groups| {
+                groups
+                    .iter()
+                    .filter_map(|g| g.as_str())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        (
+            review["spec"]["user"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            groups,
+        )
+    } else {
+        let values = |name: &str| -> Vec<String> {
+            headers
+                .iter()
+                .filter(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .collect()
+        };
+        match values("impersonate-user").pop() {
+            Some(user) => (user, values("impersonate-group")),
+            None => ("mock-admin".to_string(), vec!["system:masters".to_string()]),
+        }
+    };
+    let attributes = &review["spec"]["resourceAttributes"];
+    let verb = attributes["verb"].as_str().unwrap_or_default();
+    let resource = attributes["resource"].as_str().unwrap_or_default();
+    let allowed = groups.iter().any(|group| group == "system:masters")
+        || matches!(verb, "get" | "list" | "watch");
+    let reason = format!(
+        "user {} is {}allowed to {} {}",
+        user,
+        if allowed { "" } else { "not " },
+        verb,
+        resource
+    );
+    review["status"] = json!({"allowed": allowed, "reason": reason});
+    review
+}
+
+fn header<'a>(headers: &'a Headers, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// 以 `v4.channel.k8s.io` 协议模拟 exec, 支持的命令:
+///
+/// - `echo ARGS...`: 输出参数
+/// - `exit CODE`: 以 `CODE` 退出
+/// - `cat`: 原样输出标准输入, 终端大小变化时输出 `resize WxH`, 收到 `exit` 后退出
+/// - `kill`: 输出 `killed` 后直接关闭连接, 不发送退出状态
+/// - 其他命令返回找不到可执行文件
+fn exec(
+    mut stream: StreamOwned<ServerConnection, TcpStream>,
+    target: &str,
+    headers: &Headers,
+) -> std::io::Result<()> {
+    let key = header(headers, "sec-websocket-key").unwrap_or_default();
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\nSec-WebSocket-Protocol: v4.channel.k8s.io\r\n\r\n",
+        tungstenite::handshake::derive_accept_key(key.as_bytes())
+    )?;
+    stream.flush()?;
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    let send = |socket: &mut WebSocket<_>, channel: u8, data: &[u8]| {
+        let mut frame = vec![channel];
+        frame.extend_from_slice(data);
+        socket
+            .send(Message::Binary(frame))
+            .map_err(std::io::Error::other)
+    };
+    // apiserver 建立连接后在每个通道上发送一条空消息
+    for channel in 1..=3 {
+        send(&mut socket, channel, &[])?;
+    }
+
+    let (_, query) = target.split_once('?').unwrap_or_default();
+    let command: Vec<String> = parse_query(query)
+        .into_iter()
+        .filter(|(key, _)| key == "command")
+        .map(|(_, value)| value)
+        .collect();
+    let success = json!({"kind": "Status", "apiVersion": "v1", "status": "Success"});
+    let result = match command.first().map(String::as_str) {
+        Some("echo") => {
+            send(
+                &mut socket,
+                1,
+                format!("{}\n", command[1..].join(" ")).as_bytes(),
+            )?;
+            success
+        }
+        Some("exit") => {
+            let code = command.get(1).cloned().unwrap_or_default();
+            send(&mut socket, 2, format!("exit {}\n", code).as_bytes())?;
+            json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "status": "Failure",
+                "message": format!("command terminated with non-zero exit code: exit status {}", code),
+                "reason": "NonZeroExitCode",
+                "details": {"causes": [{"reason": "ExitCode", "message": code}]}
+            })
+        }
+        Some("cat") => {
+            loop {
+                let Ok(Message::Binary(data)) = socket.read() else {
+                    break;
+                };
+                match data.split_first() {
+                    Some((0, b"exit\n")) => break,
+                    Some((0, input)) => send(&mut socket, 1, input)?,
+                    Some((4, size)) => {
+                        let size: Value = serde_json::from_slice(size).unwrap_or_default();
+                        let message = format!("resize {}x{}\n", size["Width"], size["Height"]);
+                        send(&mut socket, 1, message.as_bytes())?;
+                    }
+                    _ => {}
+                }
+            }
+            success
+        }
+        Some("kill") => {
+            send(&mut socket, 1, b"killed\n")?;
+            return close(socket);
+        }
+        _ => json!({
+            "kind": "Status",
+            "apiVersion": "v1",
+            "status": "Failure",
+            "message": format!("exec: \"{}\": executable file not found in $PATH", command.join(" ")),
+        }),
+    };
+    send(&mut socket, 3, result.to_string().as_bytes())?;
+    close(socket)
+}
+
+fn close(mut socket: WebSocket<StreamOwned<ServerConnection, TcpStream>>) -> std::io::Result<()> {
+    let _ = socket.close(None);
+    // 等待客户端确认关闭
+    while socket.read().is_ok() {}
+    let stream = socket.get_mut();
+    stream.conn.send_close_notify();
+    stream.flush()
+}
+
+fn group_list() -> Value {
+    let mut group_versions: Vec<&str> = RESOURCES
+        .iter()
+        .map(|r| r.1)
+        .filter(|api_version| api_version.contains('/'))
+        .collect();
+    group_versions.sort();
+    group_versions.dedup();
+    let groups: Vec<Value> = group_versions
+        .into_iter()
+        .map(|group_version| {
+            let (name, version) = group_version.split_once('/').unwrap();
+            let version = json!({"groupVersion": group_version, "version": version});
+            json!({"name": name, "versions": [version], "preferredVersion": version})
+        })
+        .collect();
+    json!({"kind": "APIGroupList", "apiVersion": "v1", "groups": groups})
+}
+
+fn resource_list(group_version: &str) -> Value {
+    let resources: Vec<Value> = RESOURCES
+        .iter()
+        .filter(|r| r.1 == group_version)
+        .map(|(plural, _, kind, namespaced)| {
+            json!({
+                "name": plural,
+                "singularName": kind.to_lowercase(),
+                "namespaced": namespaced,
+                "kind": kind,
+                "verbs": ["create", "get", "list", "watch", "patch"]
+            })
+        })
+        .collect();
+    json!({
+        "kind": "APIResourceList",
+        "apiVersion": "v1",
+        "groupVersion": group_version,
+        "resources": resources
+    })
+}
+
+/// 模拟集群中的对象, 都带有 [`RESOURCE_VERSION`]
+fn objects() -> Vec<Value> {
+    let mut objects = fixtures();
+    for object in &mut objects {
+        object["metadata"]["resourceVersion"] = json!(RESOURCE_VERSION);
+    }
+    objects
+}
+
+fn fixtures() -> Vec<Value> {
+    let pod = |namespace: &str, name: &str, labels: Value, phase: &str| {
+        json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name,
+                "namespace": namespace,
+                "labels": labels,
+                "resourceVersion": RESOURCE_VERSION
+            },
+            "spec": {"containers": [{"name": "main", "image": "nginx"}]},
+            "status": {"phase": phase}
+        })
+    };
+    // `regarding` 为 `kind/object`, `event_type/reason` 为事件类型和原因
+    let event = |namespace: &str, name: &str, regarding: &str, reason: &str, count: i32| {
+        let (kind, object) = regarding.split_once('/').unwrap();
+        let (event_type, reason) = reason.split_once('/').unwrap();
+        json!({
+            "apiVersion": "events.k8s.io/v1",
+            "kind": "Event",
+            "metadata": {
+                "name": name,
+                "namespace": namespace,
+                "uid": format!("uid-{}", name),
+                "resourceVersion": RESOURCE_VERSION
+            },
+            "eventTime": "2024-01-02T03:04:05.000000Z",
+            "series": {"count": count, "lastObservedTime": "2024-01-02T03:04:05.000000Z"},
+            "type": event_type,
+            "reason": reason,
+            "note": format!("{} {}", reason, object),
+            "regarding": {"kind": kind, "name": object, "namespace": namespace},
+            "reportingController": "kubelet"
+        })
+    };
+    let namespace = |name: &str| {
+        json!({
+            "apiVersion": "v1",
+            "kind": "Namespace",
+            "metadata": {"name": name, "resourceVersion": RESOURCE_VERSION},
+            "status": {"phase": "Active"}
+        })
+    };
+    vec![
+        namespace("default"),
+        namespace("kube-system"),
+        json!({
+            "apiVersion": "v1",
+            "kind": "Node",
+            "metadata": {"name": "node-1", "labels": {"kubernetes.io/os": "linux"}},
+            "status": {"conditions": [{"type": "Ready", "status": "True"}]}
+        }),
+        pod("default", "nginx-0", json!({"app": "nginx"}), "Running"),
+        // 由控制器创建的 Pod
+        {
+            let mut pod = pod("default", "nginx-1", json!({"app": "nginx"}), "Pending");
+            pod["metadata"]["ownerReferences"] = json!([{
+                "apiVersion": "apps/v1",
+                "kind": "ReplicaSet",
+                "name": "nginx-5d8f7c",
+                "uid": "uid-nginx-5d8f7c",
+                "controller": true
+            }]);
+            pod
+        },
+        pod(
+            "kube-system",
+            "coredns-0",
+            json!({"k8s-app": "kube-dns"}),
+            "Running",
+        ),
+        event("default", "nginx-0.1", "Pod/nginx-0", "Normal/Scheduled", 1),
+        event("default", "nginx-1.1", "Pod/nginx-1", "Warning/BackOff", 3),
+        // 内容相同的另一个事件对象, 与上一个合并计数
+        event("default", "nginx-1.2", "Pod/nginx-1", "Warning/BackOff", 1),
+        event(
+            "kube-system",
+            "coredns-0.1",
+            "Pod/coredns-0",
+            "Normal/Pulled",
+            1,
+        ),
+        event(
+            "default",
+            "nginx.1",
+            "Deployment/nginx",
+            "Warning/ReplicaFailure",
+            1,
+        ),
+        json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": {"name": "kube-root-ca.crt", "namespace": "default"},
+            "data": {"ca.crt": "mock"}
+        }),
+        json!({
+            "apiVersion": "v1",
+            "kind": "Service",
+            "metadata": {
+                "name": "nginx",
+                "namespace": "default",
+                "uid": "uid-service-nginx",
+                "resourceVersion": RESOURCE_VERSION,
+                "creationTimestamp": "2024-01-02T03:04:05Z",
+                "managedFields": [{"manager": "kubectl", "operation": "Apply"}]
+            },
+            "spec": {
+                "selector": {"app": "nginx"},
+                "clusterIP": "10.96.0.10",
+                "clusterIPs": ["10.96.0.10"],
+                "ports": [{"port": 80}]
+            },
+            "status": {"loadBalancer": {}}
+        }),
+        json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "RoleBinding",
+            "metadata": {"name": "nginx", "namespace": "default"},
+            "roleRef": {"apiGroup": "rbac.authorization.k8s.io", "kind": "Role", "name": "nginx"},
+            "subjects": [{"kind": "ServiceAccount", "name": "default", "namespace": "default"}]
+        }),
+        json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": {"name": "nginx", "namespace": "default", "labels": {"app": "nginx"}},
+            "spec": {
+                "replicas": 2,
+                "selector": {"matchLabels": {"app": "nginx"}},
+                "template": {
+                    "metadata": {"labels": {"app": "nginx"}},
+                    "spec": {"containers": [{"name": "main", "image": "nginx"}]}
+                }
+            },
+            "status": {"replicas": 2, "availableReplicas": 1, "unavailableReplicas": 1}
+        }),
+    ]
+}
+
+/// 只支持 `key=value` 和 `key` 形式的标签选择器
+fn matches(object: &Value, selector: &str) -> bool {
+    selector
+        .split(',')
+        .filter(|requirement| !requirement.is_empty())
+        .all(|requirement| {
+            let labels = &object["metadata"]["labels"];
+            match requirement.split_once('=') {
+                Some((key, value)) => labels[key] == value,
+                None => !labels[requirement].is_null(),
+            }
+        })
+}
+
+/// 只支持 `field=value` 形式的字段选择器, 字段为以 `.` 分隔的路径
+fn fields_match(object: &Value, selector: &str) -> bool {
+    selector
+        .split(',')
+        .filter_map(|requirement| requirement.split_once('='))
+        .all(|(field, value)| {
+            field
+                .split('.')
+                .fold(object, |object, key| &object[key])
+                .as_str()
+                == Some(value)
+        })
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_string(), decode(value)))
+        .collect()
+}
+
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(byte) = u8::from_str_radix(
+                std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default(),
+                16,
+            ) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn ok(body: Value) -> (&'static str, &'static str, String) {
+    ("200 OK", "application/json", body.to_string())
+}
+
+fn status(code: u16, reason: &str, message: &str) -> (&'static str, &'static str, String) {
+    let line = match code {
+        400 => "400 Bad Request",
+        404 => "404 Not Found",
+        405 => "405 Method Not Allowed",
+        410 => "410 Gone",
+        429 => "429 Too Many Requests",
+        503 => "503 Service Unavailable",
+        _ => "500 Internal Server Error",
+    };
+    let body = json!({
+        "kind": "Status",
+        "apiVersion": "v1",
+        "metadata": {},
+        "status": "Failure",
+        "message": message,
+        "reason": reason,
+        "code": code
+    });
+    (line, "application/json", body.to_string())
+}
@@ -32,12 +32,800 @@ fn join_path_test() {
     )
 }
 
+mod common;
+
+/// 使用进程内的模拟 apiserver 测试, 不需要真实的 k8s 集群
+#[cfg(test)]
+mod mock_api_test {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::Duration;
+
+    use k8s_openapi::api::core::v1::{ConfigMap, Node, Pod};
+    use reqwest::Method;
+    use rust_notes::k8s::access::{AccessCheck, UserInfo};
+    use rust_notes::k8s::api::HttpClient;
+    use rust_notes::k8s::async_api::AsyncHttpClient;
+    use rust_notes::k8s::backup::{backup_namespace, restore_namespace, RestoreOptions};
+    use rust_notes::k8s::controller::{Action, Controller};
+    use rust_notes::k8s::events::EventTailer;
+    use rust_notes::k8s::exec::{ExecInput, ExecParams};
+    use rust_notes::k8s::logs::LogParams;
+    use rust_notes::k8s::manifests::{apply_manifests, ApplyAction, ApplyOptions};
+    use rust_notes::k8s::models::{HttpAuth, HttpKubeConfig};
+    use rust_notes::k8s::params::{LabelSelector, ListParams};
+    use rust_notes::k8s::report::ReportFormat;
+    use rust_notes::k8s::retry::RetryPolicy;
+    use rust_notes::k8s::service_account::ServiceAccountKubeconfig;
+    use rust_notes::k8s::watch::Event;
+
+    use super::common::MockApiServer;
+
+    fn client(server: &MockApiServer) -> HttpClient {
+        HttpClient::new(server.http_config()).unwrap()
+    }
+
+    #[test]
+    fn healthy_and_apis() {
+        let server = MockApiServer::start();
+        let http_client = client(&server);
+        assert!(http_client.healthy().unwrap());
+        assert!(http_client.apis().unwrap().contains("/apis/apps/v1"));
+        let url = http_client.url(&["version"], &[]);
+        let response = http_client.request(Method::GET, &url).unwrap().send();
+        assert!(response.unwrap().status().is_success());
+
+        // 每个 group 只出现一次
+        let url = http_client.url(&["apis"], &[]);
+        let response = http_client.request(Method::GET, &url).unwrap().send();
+        let groups: serde_json::Value = response.unwrap().json().unwrap();
+        let mut names: Vec<&str> = groups["groups"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|group| group["name"].as_str().unwrap())
+            .collect();
+        let count = names.len();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), count);
+    }
+
+    #[test]
+    fn list_and_get() {
+        let server = MockApiServer::start();
+        let http_client = client(&server);
+        assert_eq!(http_client.list::<Pod>(None, &[]).unwrap().items.len(), 3);
+        assert_eq!(
+            http_client
+                .list::<Pod>(Some("default"), &[])
+                .unwrap()
+                .items
+                .len(),
+            2
+        );
+
+        let query = ListParams::new()
+            .labels(LabelSelector::new().eq("k8s-app", "kube-dns"))
+            .query()
+            .unwrap();
+        let pods: Vec<Pod> = http_client
+            .list_all::<Pod>(None, &[&query])
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(pods.len(), 1);
+        assert_eq!(pods[0].metadata.name.as_deref(), Some("coredns-0"));
+
+        let node: Node = http_client.get(None, "node-1").unwrap();
+        assert_eq!(node.metadata.name.as_deref(), Some("node-1"));
+        let err = http_client.get::<Pod>(None, "missing").unwrap_err();
+        assert!(err.is_not_found());
+        assert!(server
+            .requests()
+            .contains(&"GET /api/v1/namespaces/default/pods/missing".to_string()));
+    }
+
+    #[test]
+    fn list_all_pages() {
+        let server = MockApiServer::start();
+        let http_client = client(&server);
+        let names = |pods: Vec<Pod>| -> Vec<String> {
+            pods.into_iter()
+                .map(|pod| pod.metadata.name.unwrap_or_default())
+                .collect()
+        };
+        let pods: Vec<Pod> = http_client
+            .list_all::<Pod>(None, &[])
+            .page_size(2)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(names(pods), vec!["nginx-0", "nginx-1", "coredns-0"]);
+        assert_eq!(
+            server.requests(),
+            vec![
+                "GET /api/v1/pods?limit=2",
+                "GET /api/v1/pods?limit=2&continue=2"
+            ]
+        );
+
+        // `args` 中的 limit 作为分页大小, 不会重复出现
+        let query = ListParams::new().limit(1).query().unwrap();
+        let pods = http_client.list_all::<Pod>(None, &[&query]);
+        assert_eq!(pods.count(), 3);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 5);
+        assert!(requests[2..]
+            .iter()
+            .all(|request| request.matches("limit=").count() == 1 && request.contains("limit=1")));
+
+        // continue token 过期时返回错误
+        let mut pager = http_client.list_all::<Pod>(None, &[]).page_size(2);
+        assert!(matches!(pager.next(), Some(Ok(_))));
+        assert!(matches!(pager.next(), Some(Ok(_))));
+        server.fail_next(&[410]);
+        let err = pager.next().unwrap().unwrap_err();
+        assert!(err.is_gone());
+        assert!(
+            err.to_string().contains("continue token expired"),
+            "{}",
+            err
+        );
+        assert!(pager.next().is_none());
+
+        // 开启 restart_on_expired 后从第一页重新查询
+        let mut pager = http_client
+            .list_all::<Pod>(None, &[])
+            .page_size(2)
+            .restart_on_expired(true);
+        assert!(matches!(pager.next(), Some(Ok(_))));
+        server.fail_next(&[410]);
+        let rest: Vec<Pod> = pager.collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            names(rest),
+            vec!["nginx-1", "nginx-0", "nginx-1", "coredns-0"]
+        );
+    }
+
+    #[test]
+    fn discovery_and_dynamic() {
+        let server = MockApiServer::start();
+        let http_client = client(&server);
+        let resource = http_client.resolve("apps/v1", "Deployment").unwrap();
+        assert_eq!(resource.plural, "deployments");
+        assert!(resource.namespaced);
+        let deployments = http_client
+            .list_dynamic("apps/v1", "Deployment", None, &[])
+            .unwrap();
+        assert_eq!(deployments.items.len(), 1);
+    }
+
+    #[test]
+    fn watch_empty_connections() {
+        let server = MockApiServer::start();
+        let config = server.http_config();
+        // 命名空间中没有 ConfigMap, 每个 watch 连接都没有事件就结束
+        std::thread::spawn(move || {
+            let http_client = HttpClient::new(config).unwrap();
+            let mut watcher = http_client.watch_from::<ConfigMap>(Some("empty"), &[], "1000");
+            watcher.next()
+        });
+        std::thread::sleep(Duration::from_millis(1200));
+        let watches = server
+            .requests()
+            .iter()
+            .filter(|r| r.starts_with("GET /api/v1/namespaces/empty/configmaps?watch=true"))
+            .count();
+        // 立即重连会有大量请求, 按退避等待 500ms, 1s 后最多 3 个
+        assert!((2..=3).contains(&watches), "{} watch requests", watches);
+    }
+
+    #[test]
+    fn watch_stop() {
+        let server = MockApiServer::start();
+        let http_client = client(&server);
+        let stopped = AtomicBool::new(false);
+        let mut watcher = http_client
+            .watch::<Pod>(Some("default"), &[])
+            .stop_on(&stopped);
+        assert!(matches!(watcher.next(), Some(Ok(Event::Restarted(_)))));
+        assert!(matches!(watcher.next(), Some(Ok(Event::Added(_)))));
+        assert!(matches!(watcher.next(), Some(Ok(Event::Added(_)))));
+        // 连接保持打开但没有新事件, 停止后等待中的 next 也会返回
+        let start = std::time::Instant::now();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(200));
+                stopped.store(true, Ordering::SeqCst);
+            });
+            assert!(watcher.next().is_none());
+        });
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(watcher.next().is_none());
+    }
+
+    #[test]
+    fn watch_and_logs() {
+        let server = MockApiServer::start();
+        let http_client = client(&server);
+        let mut watcher = http_client.watch::<Pod>(Some("default"), &[]);
+        assert!(matches!(watcher.next(), Some(Ok(Event::Restarted(pods))) if pods.len() == 2));
+        assert!(matches!(watcher.next(), Some(Ok(Event::Added(_)))));
+
+        let lines: Vec<String> = http_client
+            .logs(None, "nginx-0", &LogParams::new().tail_lines(10))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(lines, vec!["nginx-0 started", "nginx-0 ready"]);
+
+        let mut lines = vec![];
+        http_client
+            .selector_logs(
+                None,
+                &LabelSelector::new().eq("app", "nginx"),
+                &LogParams::new(),
+                |line| {
+                    lines.push(line.to_string());
+                    true
+                },
+            )
+            .unwrap();
+        lines.sort();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "[nginx-0] nginx-0 ready");
+
+        // follow 模式下日志流不会结束, 回调返回 false 后等待中的读取线程也会结束
+        let mut lines = vec![];
+        let start = std::time::Instant::now();
+        http_client
+            .selector_logs(
+                None,
+                &LabelSelector::new().eq("app", "nginx"),
+                &LogParams::new().follow(),
+                |line| {
+                    lines.push(line.to_string());
+                    lines.len() < 3
+                },
+            )
+            .unwrap();
+        assert_eq!(lines.len(), 3);
+        assert!(start.elapsed() < Duration::from_secs(20));
+
+        // 每个 Pod 的错误都会传给回调, 返回第一个错误
+        let mut lines = vec![];
+        let err = http_client
+            .selector_logs(
+                None,
+                &LabelSelector::new().eq("app", "nginx"),
+                &LogParams::new().previous(),
+                |line| {
+                    lines.push(line.to_string());
+                    true
+                },
+            )
+            .unwrap_err();
+        assert_eq!(err.code(), Some(400));
+        lines.sort();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("[nginx-0] error: "), "{}", lines[0]);
+        assert!(lines[1].contains("previous terminated container"));
+    }
+
+    #[test]
+    fn client_certificate_auth() {
+        let server = MockApiServer::start();
+        let mut config = server.http_config();
+        config.tls_server_name = "localhost".to_string();
+        assert!(HttpClient::new(config.clone()).unwrap().healthy().unwrap());
+        // 证书不包含 tls-server-name 时校验失败
+        let mut other = config.clone();
+        other.tls_server_name = "other.example".to_string();
+        assert!(HttpClient::new(other).unwrap().healthy().is_err());
+
+        // 没有客户端证书时 TLS 握手失败
+        config.client_certificate_data.clear();
+        config.client_key_data.clear();
+        assert!(HttpClient::new(config).unwrap().healthy().is_err());
+
+        // 不信任 apiserver 的 CA 时校验证书失败
+        let mut config = server.http_config();
+        config.certificate_authority_data.clear();
+        assert!(HttpClient::new(config.clone()).unwrap().healthy().is_err());
+        config.insecure_skip_tls_verify = true;
+        assert!(HttpClient::new(config).unwrap().healthy().unwrap());
+    }
+
+    #[test]
+    fn retry_and_rate_limit() {
+        let server = MockApiServer::start();
+        let http_client = client(&server).rate_limit(100.0, 1);
+        server.fail_next(&[429, 503]);
+        let pods = http_client.list::<Pod>(None, &[]).unwrap();
+        assert_eq!(pods.items.len(), 3);
+        assert_eq!(server.requests().len(), 3);
+
+        // 非幂等请求默认不重试
+        server.fail_next(&[429]);
+        let config_map = serde_json::from_value(serde_json::json!({
+            "metadata": {"name": "retry"}
+        }))
+        .unwrap();
+        let err = http_client
+            .create::<ConfigMap>(Some("default"), &config_map)
+            .unwrap_err();
+        assert_eq!(err.code(), Some(429));
+        assert_eq!(server.requests().len(), 4);
+
+        let http_client = http_client.retry_policy(RetryPolicy::none());
+        server.fail_next(&[503]);
+        let err = http_client.list::<Pod>(None, &[]).unwrap_err();
+        assert_eq!(err.code(), Some(503));
+    }
+
+    #[test]
+    fn service_account_kubeconfig() {
+        let server = MockApiServer::start();
+        let mut http_config = server.http_config();
+        http_config.tls_server_name = "localhost".to_string();
+        let http_client = HttpClient::new(http_config).unwrap();
+        let request = ServiceAccountKubeconfig::new("default", "deployer")
+            .rule(&["apps"], &["deployments"], &["get", "patch"])
+            .expiration_seconds(600);
+        let editor = http_client.service_account_kubeconfig(&request).unwrap();
+
+        let requests = server.requests();
+        for path in [
+            "PATCH /api/v1/namespaces/default/serviceaccounts/deployer",
+            "PATCH /apis/rbac.authorization.k8s.io/v1/namespaces/default/roles/deployer",
+            "PATCH /apis/rbac.authorization.k8s.io/v1/namespaces/default/rolebindings/deployer",
+            "POST /api/v1/namespaces/default/serviceaccounts/deployer/token",
+        ] {
+            assert!(requests.iter().any(|r| r.starts_with(path)), "{}", path);
+        }
+
+        let config = HttpKubeConfig::from_yaml(&editor.to_yaml().unwrap()).unwrap();
+        assert_eq!(config.server, server.server);
+        assert_eq!(config.tls_server_name, "localhost");
+        assert_eq!(config.namespace, "default");
+        assert_eq!(config.context, "deployer@kubernetes");
+        // 使用当前配置中的 CA 证书
+        assert_eq!(config.certificate_authority_data, server.ca_pem);
+        assert!(
+            matches!(config.auth, HttpAuth::Token(ref token) if token == "mock-token-deployer")
+        );
+        // 默认不强制覆盖其它 field manager 的字段
+        assert!(!requests.iter().any(|r| r.contains("force=true")));
+
+        // 没有配置 CA 证书时读取 kube-root-ca.crt
+        let mut http_config = server.http_config();
+        http_config.certificate_authority_data.clear();
+        http_config.insecure_skip_tls_verify = true;
+        let http_client = HttpClient::new(http_config).unwrap();
+        let editor = http_client
+            .service_account_kubeconfig(&request.clone().force())
+            .unwrap();
+        let config = HttpKubeConfig::from_yaml(&editor.to_yaml().unwrap()).unwrap();
+        assert_eq!(config.certificate_authority_data, "mock");
+        let forced = server
+            .requests()
+            .iter()
+            .filter(|r| r.starts_with("PATCH") && r.contains("force=true"))
+            .count();
+        assert_eq!(forced, 3);
+    }
+
+    #[test]
+    fn cluster_report() {
+        let server = MockApiServer::start();
+        let report = client(&server).cluster_report().unwrap();
+        assert_eq!(report.nodes.len(), 1);
+        assert!(report.nodes[0].ready);
+        let namespaces: Vec<(&str, usize)> = report
+            .namespaces
+            .iter()
+            .map(|namespace| (namespace.namespace.as_str(), namespace.total))
+            .collect();
+        assert_eq!(namespaces, vec![("default", 2), ("kube-system", 1)]);
+        assert_eq!(report.namespaces[0].phases["Pending"], 1);
+        assert_eq!(report.unavailable_workloads.len(), 1);
+        assert_eq!(report.unavailable_workloads[0].unavailable, 1);
+        println!("{}", report.render(ReportFormat::Table).unwrap());
+    }
+
+    #[test]
+    fn impersonation_and_access_review() {
+        let server = MockApiServer::start();
+        let admin = client(&server);
+        assert!(admin.can_i("delete", "pods", Some("default")).unwrap());
+
+        let jane = UserInfo::new("jane").group("developers");
+        let review = admin
+            .subject_access_review(&jane, &AccessCheck::new("delete", "deployments.apps"))
+            .unwrap();
+        assert!(!review.allowed);
+        assert_eq!(
+            review.to_string(),
+            "no - user jane is not allowed to delete deployments"
+        );
+
+        let impersonated = client(&server).impersonate(jane).unwrap();
+        assert!(impersonated.can_i("list", "pods", None).unwrap());
+        let review = impersonated
+            .self_access_review(&AccessCheck::new("delete", "pods").namespace("default"))
+            .unwrap();
+        assert!(!review.allowed);
+        assert_eq!(review.reason, "user jane is not allowed to delete pods");
+
+        let root = client(&server)
+            .impersonate(UserInfo::new("root").group("system:masters"))
+            .unwrap();
+        assert!(root.can_i("delete", "pods", None).unwrap());
+        assert!(client(&server)
+            .impersonate(UserInfo::default().group("system:masters"))
+            .is_err());
+    }
+
+    #[test]
+    fn exec_pod() {
+        let server = MockApiServer::start();
+        let client = client(&server);
+        let params = ExecParams::new();
+        let run = |command: &[&str]| client.exec(None, "nginx-0", command, &params)?.output();
+
+        let output = run(&["echo", "hello", "world"]).unwrap();
+        assert!(output.success());
+        assert_eq!(output.stdout_string(), "hello world\n");
+        let output = run(&["exit", "3"]).unwrap();
+        assert_eq!(output.exit_code, 3);
+        assert_eq!(output.stderr_string(), "exit 3\n");
+        let error = run(&["missing"]).unwrap_err();
+        assert!(error.to_string().contains("executable file not found"));
+        // 连接中断时没有退出状态, 不能当作成功
+        let error = run(&["kill"]).unwrap_err();
+        assert!(error.to_string().contains("without an exit status"));
+        let (_input, receiver) = mpsc::channel();
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let result = client
+            .exec(None, "nginx-0", &["kill"], &params)
+            .unwrap()
+            .interact(&receiver, &mut stdout, &mut stderr);
+        assert!(result.is_err());
+        assert_eq!(stdout, b"killed\n");
+        let Err(error) = client.exec(None, "nginx-9", &["echo"], &params) else {
+            panic!("exec in a missing pod should fail");
+        };
+        assert!(error.is_not_found());
+
+        let (input, receiver) = mpsc::channel();
+        input
+            .send(ExecInput::Resize {
+                width: 80,
+                height: 24,
+            })
+            .unwrap();
+        input.send(ExecInput::Stdin(b"hello\n".to_vec())).unwrap();
+        input.send(ExecInput::Stdin(b"exit\n".to_vec())).unwrap();
+        drop(input);
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let code = client
+            .exec(None, "nginx-0", &["cat"], &ExecParams::new().stdin().tty())
+            .unwrap()
+            .interact(&receiver, &mut stdout, &mut stderr)
+            .unwrap();
+        assert_eq!(code, 0);
+        assert_eq!(String::from_utf8(stdout).unwrap(), "resize 80x24\nhello\n");
+        assert!(stderr.is_empty());
+        assert!(server.requests().contains(
+            &"GET /api/v1/namespaces/default/pods/nginx-0/exec?command=cat&stdin=true&stdout=true&tty=true"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn tail_events() {
+        let server = MockApiServer::start();
+        let client = Arc::new(client(&server));
+        let mut lines = vec![];
+        EventTailer::new(client.clone())
+            .event_type("warning")
+            .kind("Pod")
+            .run(|line| {
+                lines.push(line.to_string());
+                line.count < 4
+            })
+            .unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                "2024-01-02T03:04:05Z default Warning BackOff pod/nginx-1: BackOff nginx-1 (x3)",
+                "2024-01-02T03:04:05Z default Warning BackOff pod/nginx-1: BackOff nginx-1 (x4)",
+            ]
+        );
+        // 只有一个值的条件由 apiserver 过滤
+        assert!(
+            server
+                .requests()
+                .iter()
+                .any(|request| request
+                    .contains("fieldSelector=type%3DWarning%2Cregarding.kind%3DPod"))
+        );
+
+        let mut lines = vec![];
+        EventTailer::new(client)
+            .namespace("default")
+            .namespace("kube-system")
+            .reason("Pulled")
+            .reason("ReplicaFailure")
+            .run(|line| {
+                lines.push(format!("{}/{}", line.namespace, line.reason));
+                lines.len() < 2
+            })
+            .unwrap();
+        lines.sort();
+        assert_eq!(lines, vec!["default/ReplicaFailure", "kube-system/Pulled"]);
+        assert!(server.requests().iter().any(|request| request
+            .starts_with("GET /apis/events.k8s.io/v1/namespaces/kube-system/events")));
+    }
+
+    #[test]
+    fn apply_manifest_dir() {
+        let server = MockApiServer::start();
+        let http_client = client(&server);
+        let dir = std::env::temp_dir().join(format!("rust-notes-apply-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("app.yaml"),
+            r###"
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: nginx
+  labels:
+    app: nginx
+spec:
+  replicas: 2
+  selector:
+    matchLabels:
+      app: nginx
+  template:
+    metadata:
+      labels:
+        app: nginx
+    spec:
+      containers:
+      - name: main
+        image: nginx
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: nginx
+spec:
+  selector:
+    app: nginx
+  ports:
+  - port: 8080
+---
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: rust-notes-manifest
+data:
+  key: value
+"###,
+        )
+        .unwrap();
+        let results = apply_manifests(&http_client, &dir, &ApplyOptions::default()).unwrap();
+        let lines: Vec<String> = results.iter().map(|result| result.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "configmap/rust-notes-manifest created",
+                "service/nginx configured",
+                "deployment.apps/nginx unchanged",
+            ]
+        );
+        assert_eq!(results[0].action, ApplyAction::Created);
+        assert!(server.requests().contains(
+            &"PATCH /apis/apps/v1/namespaces/default/deployments/nginx?fieldManager=rust-notes"
+                .to_string()
+        ));
+
+        // 不是本次创建的 CRD 定义的资源, 刷新一次该 group/version 后直接失败
+        std::fs::write(
+            dir.join("app.yaml"),
+            "apiVersion: stable.example.com/v1\nkind: CronTab\nmetadata:\n  name: cron\n",
+        )
+        .unwrap();
+        let start = std::time::Instant::now();
+        let err = apply_manifests(&http_client, &dir, &ApplyOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("CronTab"), "{}", err);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        let refreshes = server
+            .requests()
+            .iter()
+            .filter(|request| *request == "GET /apis/stable.example.com/v1")
+            .count();
+        assert_eq!(refreshes, 1);
+
+        // dry run 不会创建 CRD, 不等待其中定义的资源, 直接报告无法校验
+        std::fs::write(
+            dir.join("crd.yaml"),
+            r###"
+apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: crontabs.stable.example.com
+spec:
+  group: stable.example.com
+  scope: Namespaced
+  names:
+    kind: CronTab
+    plural: crontabs
+"###,
+        )
+        .unwrap();
+        let options = ApplyOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let start = std::time::Instant::now();
+        let results = apply_manifests(&http_client, &dir, &options).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        let lines: Vec<String> = results.iter().map(|result| result.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "customresourcedefinition.apiextensions.k8s.io/crontabs.stable.example.com created",
+                "crontab.stable.example.com/cron unverified (dry run)",
+            ]
+        );
+        assert!(!server
+            .requests()
+            .iter()
+            .any(|request| request.contains("/apis/stable.example.com/v1/")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backup_and_restore_namespace() {
+        let server = MockApiServer::start();
+        let http_client = client(&server);
+        let dir = std::env::temp_dir().join(format!("rust-notes-backup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let files = backup_namespace(&http_client, "default", &[], &dir).unwrap();
+        let mut names: Vec<String> = files
+            .iter()
+            .map(|file| file.strip_prefix(&dir).unwrap().display().to_string())
+            .collect();
+        names.sort();
+        // 跳过 Event, 由 ReplicaSet 创建的 nginx-1 和自动生成的 kube-root-ca.crt
+        assert_eq!(
+            names,
+            vec![
+                "deployments.apps/nginx.yaml",
+                "namespaces/default.yaml",
+                "pods/nginx-0.yaml",
+                "rolebindings.rbac.authorization.k8s.io/nginx.yaml",
+                "services/nginx.yaml",
+            ]
+        );
+        let service = std::fs::read_to_string(dir.join("services/nginx.yaml")).unwrap();
+        for field in [
+            "status",
+            "managedFields",
+            "resourceVersion",
+            "uid",
+            "clusterIP",
+        ] {
+            assert!(!service.contains(field), "{}", service);
+        }
+        assert!(service.contains("namespace: default"));
+        let deployments = backup_namespace(&http_client, "default", &["deployments.apps"], &dir);
+        assert_eq!(deployments.unwrap().len(), 2);
+
+        let options = RestoreOptions {
+            namespace: Some("restored".to_string()),
+            ..Default::default()
+        };
+        let results = restore_namespace(&http_client, &dir, &options).unwrap();
+        let applied: Vec<String> = results
+            .iter()
+            .map(|result| format!("{}/{}", result.kind, result.name))
+            .collect();
+        assert_eq!(applied[0], "Namespace/restored");
+        assert_eq!(applied.len(), 5);
+        let requests = server.requests();
+        for path in [
+            "/api/v1/namespaces/restored?",
+            "/api/v1/namespaces/restored/services/nginx?",
+            "/apis/apps/v1/namespaces/restored/deployments/nginx?",
+            "/apis/rbac.authorization.k8s.io/v1/namespaces/restored/rolebindings/nginx?",
+        ] {
+            assert!(
+                requests
+                    .iter()
+                    .any(|request| request.starts_with(&format!("PATCH {}", path))),
+                "{:?}",
+                requests
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn controller_reconcile() {
+        let server = MockApiServer::start();
+        let controller = Controller::<Pod>::new(Arc::new(client(&server)), Some("default"), &[])
+            .workers(2)
+            .backoff(Duration::from_millis(10), Duration::from_millis(100));
+        let queue = controller.queue();
+        let calls = Mutex::new(HashMap::<String, usize>::new());
+        controller.run(|pod, store| {
+            let name = pod.metadata.name.clone().unwrap();
+            let mut calls = calls.lock().unwrap();
+            let count = calls.entry(name.clone()).or_default();
+            *count += 1;
+            if name == "nginx-0" && *count == 1 {
+                return Err(format!("{} not ready", name));
+            }
+            assert_eq!(store.by_label("app", "nginx").len(), 2);
+            if calls.get("nginx-0") >= Some(&2) && calls.contains_key("nginx-1") {
+                queue.shutdown();
+            }
+            Ok(Action::Done)
+        });
+        let calls = calls.into_inner().unwrap();
+        assert!(calls["nginx-0"] >= 2);
+        assert!(calls["nginx-1"] >= 1);
+    }
+
+    #[tokio::test]
+    async fn async_list() {
+        let server = MockApiServer::start();
+        let http_client = AsyncHttpClient::new(server.http_config()).unwrap();
+        assert!(http_client.healthy().await.unwrap());
+        let pods = http_client.list::<Pod>(None, &[]).await.unwrap();
+        assert_eq!(pods.items.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn async_retry_and_rate_limit() {
+        let server = MockApiServer::start();
+        let http_client = AsyncHttpClient::new(server.http_config())
+            .unwrap()
+            .rate_limit(100.0, 1);
+        server.fail_next(&[429, 503]);
+        let pods = http_client.list::<Pod>(None, &[]).await.unwrap();
+        assert_eq!(pods.items.len(), 3);
+        assert_eq!(server.requests().len(), 3);
+
+        let http_client = http_client.retry_policy(RetryPolicy::none());
+        server.fail_next(&[503]);
+        let err = http_client.list::<Pod>(None, &[]).await.unwrap_err();
+        assert_eq!(err.code(), Some(503));
+    }
+}
+
 #[cfg(test)]
 #[cfg(feature = "local_runtime")]
 mod k8s_api_test {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::{ConfigMap, Namespace, Node, Pod};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use reqwest::Method;
     use rust_notes::k8s::api::HttpClient;
+    use rust_notes::k8s::async_api::AsyncHttpClient;
+    use rust_notes::k8s::logs::LogParams;
+    use rust_notes::k8s::manifests::{apply_manifests, ApplyOptions};
     use rust_notes::k8s::models::HttpKubeConfig;
-    use rust_notes::utils::json::JsonConverter;
+    use rust_notes::k8s::params::{LabelSelector, ListParams};
+    use rust_notes::k8s::patch::{Patch, PatchParams};
+    use rust_notes::k8s::watch::Event;
 
     const KUBE_CONFIG_FILE: &str = "E:\\etc\\k8s\\kubeconfig";
 
@@ -51,8 +839,8 @@ mod k8s_api_test {
     #[test]
     fn check_url() {
         assert_eq!(check(), true);
-        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE);
-        let http_client = HttpClient::new(config);
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
         assert_eq!(
             http_client
                 .url(&["api", "v1", "pods"], &[])
@@ -64,43 +852,45 @@ mod k8s_api_test {
     #[test]
     fn apis_list() {
         assert_eq!(check(), true);
-        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE);
-        let http_client = HttpClient::new(config);
-        println!("{}", http_client.apis());
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
+        println!("{}", http_client.apis().unwrap());
     }
 
     #[test]
     fn api_groups() {
         assert_eq!(check(), true);
-        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE);
-        let http_client = HttpClient::new(config);
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
         let url = http_client.url(&["/api/v1"], &[]);
-        let response = http_client.client.get(url).send().unwrap();
+        let response = http_client
+            .request(Method::GET, &url)
+            .unwrap()
+            .send()
+            .unwrap();
         println!("{}", response.text().unwrap())
     }
 
     #[test]
     fn pods() {
         assert_eq!(check(), true);
-        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE);
-        let http_client = HttpClient::new(config);
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
         // kubectl get po -n kube-system --field-selector=status.phase=Running
         // /api/v1/namespaces/kube-system/pods?fieldSelector=status.phase%3DRunning&limit=500
         //
         // kubectl get po -n kube-system -l tier=control-plane
-        let url = http_client.url(
-            &["/api/v1/namespaces", "kube-system", "pods"],
-            &["labelSelector=tier%3Dcontrol-plane", "limit=500"],
-        );
-        println!("{}", url);
-        let response = http_client.client.get(url).send().unwrap();
-        let json_obj: serde_json::Value = JsonConverter::convert_object(&response.text().unwrap());
-        let default_item = serde_json::json!([]);
-        let items = json_obj.get("items").unwrap_or(&default_item);
-        for pod in items.as_array().unwrap() {
+        let params = ListParams::new().labels(LabelSelector::new().eq("tier", "control-plane"));
+        let query = params.query().unwrap();
+        let pods = http_client
+            .list_all::<Pod>(Some("kube-system"), &[&query])
+            .page_size(100);
+        for pod in pods {
+            let pod = pod.unwrap();
             println!(
-                "Pod: {} -> {}",
-                pod["metadata"]["name"], pod["metadata"]["labels"]["tier"]
+                "Pod: {} -> {:?}",
+                pod.metadata.name.unwrap_or_default(),
+                pod.metadata.labels.unwrap_or_default().get("tier")
             )
         }
     }
@@ -108,20 +898,172 @@ mod k8s_api_test {
     #[test]
     fn namespaces() {
         assert_eq!(check(), true);
-        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE);
-        let http_client = HttpClient::new(config);
-        let url = http_client.url(&["/api/v1/namespaces"], &["limit=500"]);
-        let response = http_client.client.get(url).send().unwrap();
-        println!("{}", response.text().unwrap());
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
+        let namespaces = http_client.list::<Namespace>(None, &["limit=500"]).unwrap();
+        for namespace in namespaces.items {
+            println!("Namespace: {}", namespace.metadata.name.unwrap_or_default());
+        }
+        let namespace = http_client.get::<Namespace>(None, "kube-system").unwrap();
+        assert_eq!(namespace.metadata.name.as_deref(), Some("kube-system"));
     }
 
     #[test]
     fn nodes() {
         assert_eq!(check(), true);
-        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE);
-        let http_client = HttpClient::new(config);
-        let url = http_client.url(&["/api/v1/nodes"], &["limit=500"]);
-        let response = http_client.client.get(url).send().unwrap();
-        println!("{}", response.text().unwrap());
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
+        let nodes = http_client.list::<Node>(None, &["limit=500"]).unwrap();
+        for node in nodes.items {
+            println!("Node: {}", node.metadata.name.unwrap_or_default());
+        }
+    }
+
+    #[test]
+    fn config_maps() {
+        assert_eq!(check(), true);
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
+        let mut config_map = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some("rust-notes-test".to_string()),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from([("key".to_string(), "value".to_string())])),
+            ..Default::default()
+        };
+        let created = http_client.create(Some("default"), &config_map).unwrap();
+        config_map.metadata.resource_version = created.metadata.resource_version;
+        config_map.data = Some(BTreeMap::from([("key".to_string(), "changed".to_string())]));
+        let replaced = http_client
+            .replace(Some("default"), "rust-notes-test", &config_map)
+            .unwrap();
+        assert_eq!(replaced.data.unwrap()["key"], "changed");
+        http_client
+            .delete::<ConfigMap>(Some("default"), "rust-notes-test")
+            .unwrap();
+    }
+
+    #[test]
+    fn watch_pods() {
+        assert_eq!(check(), true);
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
+        for event in http_client.watch::<Pod>(Some("kube-system"), &[]).take(3) {
+            match event.unwrap() {
+                Event::Restarted(pods) => println!("Restarted: {} pods", pods.len()),
+                Event::Added(pod) => println!("Added: {:?}", pod.metadata.name),
+                Event::Modified(pod) => println!("Modified: {:?}", pod.metadata.name),
+                Event::Deleted(pod) => println!("Deleted: {:?}", pod.metadata.name),
+                Event::Bookmark(version) => println!("Bookmark: {}", version),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn async_pods() {
+        assert_eq!(check(), true);
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = AsyncHttpClient::new(config).unwrap();
+        assert!(http_client.healthy().await.unwrap());
+        let pods = http_client
+            .list::<Pod>(Some("kube-system"), &[])
+            .await
+            .unwrap();
+        for pod in pods.items {
+            println!("Pod: {}", pod.metadata.name.unwrap_or_default());
+        }
+    }
+
+    #[test]
+    fn dynamic_resources() {
+        assert_eq!(check(), true);
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
+        let deployments = http_client
+            .list_dynamic("apps/v1", "Deployment", Some("kube-system"), &[])
+            .unwrap();
+        for deployment in deployments.items {
+            println!(
+                "Deployment: {}",
+                deployment.metadata.name.unwrap_or_default()
+            );
+        }
+        let resource = http_client.resolve("v1", "Node").unwrap();
+        assert!(!resource.namespaced);
+    }
+
+    #[test]
+    fn patch_resources() {
+        assert_eq!(check(), true);
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
+        let patch = Patch::Merge(serde_json::json!({
+            "metadata": {"labels": {"rust-notes": "patched"}}
+        }));
+        let namespace: Namespace = http_client
+            .patch(None, "default", &PatchParams::default(), &patch)
+            .unwrap();
+        assert_eq!(namespace.metadata.labels.unwrap()["rust-notes"], "patched");
+
+        let apply = Patch::Apply(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": {"name": "rust-notes-apply"},
+            "data": {"key": "value"}
+        }));
+        let config_map: ConfigMap = http_client
+            .patch(
+                Some("default"),
+                "rust-notes-apply",
+                &PatchParams::apply("rust-notes").force(),
+                &apply,
+            )
+            .unwrap();
+        assert_eq!(config_map.data.unwrap()["key"], "value");
+    }
+
+    #[test]
+    fn apply_manifest_files() {
+        assert_eq!(check(), true);
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
+        let dir = std::env::temp_dir().join("rust-notes-manifests");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("app.yaml"),
+            "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: rust-notes-manifest\n  namespace: rust-notes\ndata:\n  key: value\n---\napiVersion: v1\nkind: Namespace\nmetadata:\n  name: rust-notes\n",
+        )
+        .unwrap();
+        let results = apply_manifests(&http_client, &dir, &ApplyOptions::default()).unwrap();
+        for result in &results {
+            println!("{}", result);
+        }
+        assert_eq!(results[0].kind, "Namespace");
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn pod_logs() {
+        assert_eq!(check(), true);
+        let config = HttpKubeConfig::read_from(KUBE_CONFIG_FILE).unwrap();
+        let http_client = HttpClient::new(config).unwrap();
+        let pods = http_client.list::<Pod>(Some("kube-system"), &[]).unwrap();
+        let name = pods.items[0].metadata.name.clone().unwrap();
+        let params = LogParams::new().tail_lines(10).timestamps();
+        for line in http_client
+            .logs(Some("kube-system"), &name, &params)
+            .unwrap()
+        {
+            println!("{}", line.unwrap());
+        }
+
+        let selector = LabelSelector::new().eq("k8s-app", "kube-dns");
+        http_client
+            .selector_logs(Some("kube-system"), &selector, &params, |line| {
+                println!("{}", line);
+                true
+            })
+            .unwrap();
     }
 }